@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use ab_glyph::{FontRef, PxScale};
+use imageproc::drawing::{draw_text_mut, text_size};
+use imageproc::image::{ImageBuffer, Rgba};
+use serde::{Deserialize, Serialize};
+
+use crate::core::audio_process::{blend_over, fill_span};
+use crate::core::ViewSignal;
+use crate::layout::TimeLayout;
+use crate::style::AmplitudeAxis;
+use crate::svg::{format_timestamp, tick_step};
+
+static FONT: &[u8] = include_bytes!("ressources/DejaVuSansMono.ttf");
+
+// Levels drawn by `AmplitudeGrid::Decibel`, in dBFS of the source.
+const DECIBEL_LINES: [f32; 9] = [0.0, -3.0, -6.0, -12.0, -18.0, -24.0, -36.0, -48.0, -60.0];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AmplitudeGrid {
+    #[default]
+    None,
+    // Lines at half and full lane height, labelled with the source amplitude.
+    Linear,
+    // Lines at fixed dBFS levels, as many as fit without labels overlapping.
+    Decibel,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Annotations {
+    pub time_axis: bool,
+    // Vertical lines across the whole image at every time tick.
+    pub time_grid: bool,
+    pub amplitude_grid: AmplitudeGrid,
+    pub labels: bool,
+    pub font_size: f32,
+    pub target_ticks: usize,
+    pub grid_color: [u8; 4],
+    pub text_color: [u8; 4],
+}
+
+impl Default for Annotations {
+    fn default() -> Self {
+        Annotations {
+            time_axis: true,
+            time_grid: false,
+            amplitude_grid: AmplitudeGrid::None,
+            labels: true,
+            font_size: 12.0,
+            target_ticks: 10,
+            grid_color: [128, 128, 128, 96],
+            text_color: [64, 64, 64, 255],
+        }
+    }
+}
+
+pub(crate) fn font() -> FontRef<'static> {
+    FontRef::try_from_slice(FONT).expect("embedded font is valid")
+}
+
+impl ViewSignal {
+    // Draws the annotation layer over the rendered image. `layout` tells
+    // which time each column shows, usually `TimeLayout::fit` of the
+    // rendered range and width.
+    pub fn annotate(&mut self, layout: &TimeLayout, annotations: &Annotations) {
+        let font = font();
+        let scale = PxScale::from(annotations.font_size);
+
+        if annotations.amplitude_grid != AmplitudeGrid::None {
+            let lanes = self.lanes();
+            let levels = grid_levels(
+                annotations.amplitude_grid,
+                self.gain(),
+                self.amplitude_axis(),
+            );
+
+            for lane in lanes {
+                let mut last_y = f32::NEG_INFINITY;
+                for (level, label) in &levels {
+                    let offset = lane.half_height * level;
+                    let y = lane.center - offset;
+                    if y - last_y < annotations.font_size {
+                        continue;
+                    }
+                    last_y = y;
+
+                    let image = self.image_mut();
+                    draw_row(image, y, annotations.grid_color);
+                    draw_row(image, lane.center + offset, annotations.grid_color);
+                    if annotations.labels {
+                        let (_, text_height) = text_size(scale, &font, label);
+                        let top = (y as i32 - text_height as i32 - 1).max(lane.top as i32);
+                        draw_text_mut(
+                            image,
+                            Rgba(annotations.text_color),
+                            2,
+                            top,
+                            scale,
+                            &font,
+                            label,
+                        );
+                    }
+                }
+            }
+        }
+
+        if annotations.time_axis || annotations.time_grid {
+            self.draw_time_axis(layout, annotations, &font, scale);
+        }
+    }
+
+    fn draw_time_axis(
+        &mut self,
+        layout: &TimeLayout,
+        annotations: &Annotations,
+        font: &FontRef,
+        scale: PxScale,
+    ) {
+        let image = self.image_mut();
+        let (width, height) = image.dimensions();
+        let range = layout.visible_range(width as usize);
+        let (start, end) = (range.start.as_secs_f64(), range.end.as_secs_f64());
+        if end <= start {
+            return;
+        }
+
+        let step = tick_step(end - start, annotations.target_ticks);
+        let tick_height = (annotations.font_size / 2.0).max(2.0);
+        let bottom = height as f32;
+
+        let mut tick = (start / step).ceil() * step;
+        while tick < end {
+            let time = Duration::from_secs_f64(tick);
+            let x = layout.x_at(time).round() as i64;
+
+            if annotations.time_grid {
+                fill_span(
+                    image,
+                    x,
+                    0.0,
+                    bottom,
+                    |_| Rgba(annotations.grid_color),
+                    false,
+                );
+            }
+            if annotations.time_axis {
+                fill_span(
+                    image,
+                    x,
+                    bottom - tick_height,
+                    bottom,
+                    |_| Rgba(annotations.text_color),
+                    false,
+                );
+
+                if annotations.labels {
+                    let label = format_timestamp(time);
+                    let (text_width, text_height) = text_size(scale, font, &label);
+                    let left = (x - text_width as i64 / 2)
+                        .clamp(0, (width as i64 - text_width as i64).max(0));
+                    let top = bottom - tick_height - text_height as f32 - 1.0;
+                    draw_text_mut(
+                        image,
+                        Rgba(annotations.text_color),
+                        left as i32,
+                        top as i32,
+                        scale,
+                        font,
+                        &label,
+                    );
+                }
+            }
+
+            tick += step;
+        }
+    }
+}
+
+// Fractions of the lane half-height with their labels, top line first.
+fn grid_levels(grid: AmplitudeGrid, gain: f32, axis: AmplitudeAxis) -> Vec<(f32, String)> {
+    let gain = if gain.is_finite() && gain > 0.0 {
+        gain
+    } else {
+        1.0
+    };
+
+    match grid {
+        AmplitudeGrid::None => Vec::new(),
+        AmplitudeGrid::Linear => [1.0, 0.5]
+            .into_iter()
+            .map(|level| (level, format!("{:.2}", axis.unscale(level) / gain)))
+            .collect(),
+        AmplitudeGrid::Decibel => DECIBEL_LINES
+            .into_iter()
+            .map(|db| (axis.scale(gain * 10f32.powf(db / 20.0)), format!("{db} dB")))
+            .filter(|(level, _)| *level > 0.0 && *level <= 1.0)
+            .collect(),
+    }
+}
+
+fn draw_row(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, y: f32, color: [u8; 4]) {
+    let y = y.round();
+    if y < 0.0 || y >= image.height() as f32 {
+        return;
+    }
+
+    for x in 0..image.width() {
+        blend_over(image.get_pixel_mut(x, y as u32), Rgba(color), 1.0);
+    }
+}
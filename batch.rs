@@ -0,0 +1,329 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use imageproc::image::ImageFormat;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{MySample, ViewSignal};
+use crate::error::Error;
+use crate::style::WaveformStyle;
+use crate::svg::{SvgOptions, SvgSignal};
+
+// Extensions the decoder handles, compared without case.
+const AUDIO_EXTENSIONS: [&str; 7] = ["mp3", "wav", "flac", "ogg", "oga", "m4a", "aac"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Bmp,
+    Tiff,
+    Svg,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchOptions {
+    pub style: WaveformStyle,
+    pub format: OutputFormat,
+    // Threads rendering at the same time; 0 uses one per core.
+    pub workers: usize,
+    pub recursive: bool,
+    // Renders again even when the output is newer than its source.
+    pub force: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            style: WaveformStyle::default(),
+            format: OutputFormat::default(),
+            workers: 0,
+            recursive: true,
+            force: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchStatus {
+    Rendered,
+    // The output was already newer than the source.
+    Skipped,
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchEntry {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub status: BatchStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchReport {
+    pub rendered: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    // In the order the directory walk found the sources.
+    pub files: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn try_save(&self, file_name: &str) -> Result<(), Error> {
+        fs::write(file_name, self.to_json()?)?;
+
+        Ok(())
+    }
+}
+
+impl BatchOptions {
+    // Renders every audio file under `input_dir` to the same relative path
+    // under `output_dir`, with the output extension after its own so that
+    // `a.wav` and `a.flac` don't share `a.wav.png`. A file that fails is recorded in the report and
+    // the others go on; only a directory that cannot be read is an error.
+    pub fn run(&self, input_dir: &Path, output_dir: &Path) -> Result<BatchReport, Error> {
+        self.run_with_progress(input_dir, output_dir, |_, _, _| {})
+    }
+
+    // `progress` gets each finished entry with the number of entries done
+    // so far and the total, from the worker threads.
+    pub fn run_with_progress(
+        &self,
+        input_dir: &Path,
+        output_dir: &Path,
+        progress: impl Fn(&BatchEntry, usize, usize) + Sync,
+    ) -> Result<BatchReport, Error> {
+        let mut sources = Vec::new();
+        find_audio_files(input_dir, self.recursive, &mut sources)?;
+        sources.sort();
+
+        let jobs: Vec<(PathBuf, PathBuf)> = sources
+            .into_iter()
+            .map(|source| {
+                let relative = source.strip_prefix(input_dir).unwrap_or(&source);
+                let mut output = output_dir.join(relative).into_os_string();
+                output.push(".");
+                output.push(self.format.extension());
+                (source, PathBuf::from(output))
+            })
+            .collect();
+
+        let workers = match self.workers {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            workers => workers,
+        }
+        .min(jobs.len())
+        .max(1);
+
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let entries = Mutex::new(vec![None; jobs.len()]);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((source, output)) = jobs.get(index) else {
+                        break;
+                    };
+
+                    let entry = self.process(source, output);
+                    let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress(&entry, finished, jobs.len());
+                    entries.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(entry);
+                });
+            }
+        });
+
+        let mut report = BatchReport::default();
+        for entry in entries.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            let Some(entry) = entry else {
+                continue;
+            };
+            match entry.status {
+                BatchStatus::Rendered => report.rendered += 1,
+                BatchStatus::Skipped => report.skipped += 1,
+                BatchStatus::Failed => report.failed += 1,
+            }
+            report.files.push(entry);
+        }
+
+        Ok(report)
+    }
+
+    fn process(&self, source: &Path, output: &Path) -> BatchEntry {
+        let status = if !self.force && is_up_to_date(source, output) {
+            Ok(BatchStatus::Skipped)
+        } else {
+            self.render(source, output).map(|_| BatchStatus::Rendered)
+        };
+
+        let (status, error) = match status {
+            Ok(status) => (status, None),
+            Err(e) => (BatchStatus::Failed, Some(e.to_string())),
+        };
+        BatchEntry {
+            source: source.to_path_buf(),
+            output: output.to_path_buf(),
+            status,
+            error,
+        }
+    }
+
+    // Written next to the output first and renamed, so that an interrupted
+    // run never leaves a partial file that looks up to date.
+    fn render(&self, source: &Path, output: &Path) -> Result<(), Error> {
+        let sample = MySample::try_from_reader(BufReader::new(File::open(source)?))?;
+
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial = output.with_extension(format!("{}.part", self.format.extension()));
+
+        let written = match self.format {
+            OutputFormat::Svg => {
+                let options = SvgOptions {
+                    view_box: true,
+                    time_axis: false,
+                };
+                SvgSignal::try_from_sample(&sample, &self.style, options)
+                    .and_then(|svg| Ok(fs::write(&partial, svg.as_str())?))
+            }
+            OutputFormat::Png | OutputFormat::Bmp | OutputFormat::Tiff => {
+                let format = match self.format {
+                    OutputFormat::Bmp => ImageFormat::Bmp,
+                    OutputFormat::Tiff => ImageFormat::Tiff,
+                    _ => ImageFormat::Png,
+                };
+                ViewSignal::try_with_style(
+                    &sample.samples,
+                    sample.channels as usize,
+                    sample.sample_rate,
+                    &self.style,
+                )
+                .and_then(|view| {
+                    let mut file = BufWriter::new(File::create(&partial)?);
+                    view.try_write_to(&mut file, format)
+                })
+            }
+        };
+
+        match written.and_then(|_| Ok(fs::rename(&partial, output)?)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                Err(e)
+            }
+        }
+    }
+}
+
+fn find_audio_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // Does not follow symlinks, so a link back up the tree can't make
+        // the walk loop. Linked files still count.
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if recursive {
+                find_audio_files(&path, recursive, files)?;
+            }
+        } else if (file_type.is_file() || path.is_file()) && is_audio_file(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+fn is_up_to_date(source: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified());
+
+    match (modified(source), modified(output)) {
+        (Ok(source), Ok(output)) => output >= source,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn batch_skips_fresh_outputs_and_reports_failures() {
+        let root = std::env::temp_dir().join(format!("sound-wave-batch-{}", std::process::id()));
+        let (input, output) = (root.join("in"), root.join("out"));
+        fs::create_dir_all(input.join("album")).unwrap();
+        fs::create_dir_all(output.join("album")).unwrap();
+        fs::write(input.join("broken.wav"), b"not audio").unwrap();
+        fs::write(input.join("album/done.MP3"), b"not audio").unwrap();
+        fs::write(input.join("notes.txt"), b"ignored").unwrap();
+        fs::write(output.join("album/done.MP3.png"), b"rendered before").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("..", input.join("album/back")).unwrap();
+
+        let report = BatchOptions::default().run(&input, &output).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!((report.rendered, report.skipped, report.failed), (0, 1, 1));
+        assert_eq!(report.files[0].source, input.join("album/done.MP3"));
+        assert_eq!(report.files[0].status, BatchStatus::Skipped);
+        assert_eq!(report.files[1].status, BatchStatus::Failed);
+        assert!(report.files[1].error.is_some());
+        assert!(!output.join("broken.wav.png.part").exists());
+    }
+
+    #[test]
+    fn sources_differing_in_extension_get_their_own_outputs() {
+        let root = std::env::temp_dir().join(format!("sound-wave-names-{}", std::process::id()));
+        let (input, output) = (root.join("in"), root.join("out"));
+        fs::create_dir_all(&input).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        fs::write(input.join("a.flac"), b"not audio").unwrap();
+        fs::write(input.join("a.wav"), b"not audio").unwrap();
+        fs::write(output.join("a.wav.png"), b"rendered before").unwrap();
+
+        let options = BatchOptions {
+            workers: 2,
+            ..Default::default()
+        };
+        let report = options.run(&input, &output).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.files[0].output, output.join("a.flac.png"));
+        assert_eq!(report.files[0].status, BatchStatus::Failed);
+        assert_eq!(report.files[1].output, output.join("a.wav.png"));
+        assert_eq!(report.files[1].status, BatchStatus::Skipped);
+    }
+}
@@ -0,0 +1,56 @@
+// Run with and without `--features parallel` to compare.
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use sound_wave_image::{Normalization, ViewSignal, WaveformStyle};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+// Stereo sine sweep with some noise, `minutes` long.
+fn sound(minutes: u32) -> Vec<f32> {
+    let frames = (minutes * 60 * SAMPLE_RATE) as usize;
+    let mut seed = 0x2545_f491_u32;
+
+    (0..frames)
+        .flat_map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let tone = (2.0 * std::f32::consts::PI * (110.0 + t) * t).sin() * 0.6;
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let noise = seed as f32 / u32::MAX as f32 * 0.2 - 0.1;
+            [tone + noise, tone - noise]
+        })
+        .collect()
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(20));
+
+    for minutes in [3, 10] {
+        let sound = sound(minutes);
+
+        for size in [[2_000, 200], [16_000, 800]] {
+            let style = WaveformStyle {
+                size,
+                normalization: Normalization::Gain(1.0),
+                ..Default::default()
+            };
+            let id = format!("{minutes}min {}x{}", size[0], size[1]);
+
+            group.bench_with_input(BenchmarkId::from_parameter(id), &style, |b, style| {
+                b.iter(|| {
+                    ViewSignal::try_with_style(black_box(&sound), 2, SAMPLE_RATE, style).unwrap()
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,169 @@
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+
+use crate::core::audio_process::column_range;
+
+// Frames per FFT when measuring the spectral centroid of a column.
+const CENTROID_BLOCK: usize = 1024;
+// Centroids at or below the first frequency are red, at or above the last
+// blue, and green in between on a log scale.
+const CENTROID_RANGE: [f32; 2] = [150.0, 6000.0];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ColorMode {
+    // `wave_color` everywhere.
+    #[default]
+    Solid,
+    // From the top of each lane to its bottom.
+    VerticalGradient {
+        top: [u8; 4],
+        bottom: [u8; 4],
+    },
+    // From the first column to the last.
+    HorizontalGradient {
+        start: [u8; 4],
+        end: [u8; 4],
+    },
+    // By the drawn peak of each column, 0 to full lane height.
+    Amplitude {
+        quiet: [u8; 4],
+        loud: [u8; 4],
+    },
+    // By the RMS level of each column, `floor_db` to 0 dB.
+    Loudness {
+        quiet: [u8; 4],
+        loud: [u8; 4],
+        floor_db: f32,
+    },
+    // By the spectral centroid of each column: bass red, mids green, highs
+    // blue. Needs the audio, so a render from peaks falls back to `Solid`.
+    SpectralCentroid,
+}
+
+pub(crate) fn mix(from: [u8; 4], to: [u8; 4], t: f32) -> [u8; 4] {
+    let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+    let mut color = [0; 4];
+    for c in 0..4 {
+        color[c] = (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t).round() as u8;
+    }
+    color
+}
+
+pub(crate) fn centroid_color(centroid: f32, alpha: u8) -> [u8; 4] {
+    let [low, high] = CENTROID_RANGE;
+    let t = (centroid.max(low) / low).ln() / (high / low).ln();
+
+    if t < 0.5 {
+        mix([255, 0, 0, alpha], [0, 255, 0, alpha], t * 2.0)
+    } else {
+        mix([0, 255, 0, alpha], [0, 0, 255, alpha], t * 2.0 - 1.0)
+    }
+}
+
+// Spectral centroid in Hz of every column, from samples pushed in order.
+// Each column is cut into blocks of `CENTROID_BLOCK` frames, the last one
+// zero-padded, so the result does not depend on how samples arrive.
+pub(crate) struct CentroidAccumulator {
+    width: usize,
+    sample_len: usize,
+    sample_rate: u32,
+    position: usize,
+    column: usize,
+    block: Vec<f32>,
+    weighted: f64,
+    magnitude: f64,
+    centroids: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+}
+
+impl CentroidAccumulator {
+    pub(crate) fn new(width: usize, sample_len: usize, sample_rate: u32) -> Self {
+        CentroidAccumulator {
+            width,
+            sample_len,
+            sample_rate,
+            position: 0,
+            column: 0,
+            block: Vec::with_capacity(CENTROID_BLOCK),
+            weighted: 0.0,
+            magnitude: 0.0,
+            centroids: Vec::with_capacity(width),
+            fft: FftPlanner::<f32>::new().plan_fft_forward(CENTROID_BLOCK),
+            window: (0..CENTROID_BLOCK)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / CENTROID_BLOCK as f32).cos())
+                .collect(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, s: f32) {
+        if self.column >= self.width {
+            return;
+        }
+
+        self.block.push(s);
+        self.position += 1;
+        if self.block.len() == CENTROID_BLOCK {
+            self.analyze_block();
+        }
+
+        // Columns sharing their only sample with the next one repeat it.
+        while self.column < self.width
+            && column_range(self.column, self.width, self.sample_len).end <= self.position
+        {
+            if !self.block.is_empty() {
+                self.analyze_block();
+            }
+            self.centroids.push(if self.magnitude > 0.0 {
+                (self.weighted / self.magnitude) as f32
+            } else {
+                self.centroids.last().copied().unwrap_or(0.0)
+            });
+            self.weighted = 0.0;
+            self.magnitude = 0.0;
+            self.column += 1;
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Vec<f32> {
+        let last = self.centroids.last().copied().unwrap_or(0.0);
+        self.centroids.resize(self.width, last);
+        self.centroids
+    }
+
+    fn analyze_block(&mut self) {
+        let mut buffer: Vec<Complex<f32>> = self
+            .window
+            .iter()
+            .enumerate()
+            .map(|(i, w)| Complex::new(self.block.get(i).copied().unwrap_or(0.0) * w, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+        self.block.clear();
+
+        let bin_width = self.sample_rate as f64 / CENTROID_BLOCK as f64;
+        for (k, bin) in buffer[1..=CENTROID_BLOCK / 2].iter().enumerate() {
+            let magnitude = bin.norm() as f64;
+            self.weighted += (k + 1) as f64 * bin_width * magnitude;
+            self.magnitude += magnitude;
+        }
+    }
+}
+
+pub(crate) fn spectral_centroids(
+    frames: Range<usize>,
+    value: impl Fn(usize) -> f32,
+    width: usize,
+    sample_rate: u32,
+) -> Vec<f32> {
+    let mut accumulator = CentroidAccumulator::new(width, frames.len(), sample_rate);
+    for i in frames {
+        accumulator.push(value(i));
+    }
+    accumulator.finish()
+}
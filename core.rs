@@ -1,63 +1,425 @@
-use audio_open::MySample;
-use imageproc::image;
-use rodio::Sample;
-pub use visual_signal::ViewSignal;
+pub use audio_open::MySample;
+pub(crate) use visual_signal::{blank_image, image_len};
+pub use visual_signal::{ChannelMode, ViewSignal};
 
 mod visual_signal {
-    use std::fmt::{Debug, Display};
-    use std::ops::{AddAssign, Div, Mul};
+    use std::io::{Seek, Write};
+    use std::ops::Range;
+    use std::time::Duration;
 
-    use cpal::{FromSample, Sample, SampleFormat, SizedSample};
-    use imageproc::drawing::{draw_antialiased_line_segment_mut, Canvas};
-    use imageproc::image::{DynamicImage, ImageBuffer, Pixel, Rgb};
-    use imageproc::pixelops::interpolate;
+    use imageproc::image::{ImageBuffer, ImageFormat, Rgba};
+    use serde::{Deserialize, Serialize};
 
-    use self::audio_process::draw_wave;
+    use self::audio_process::{draw_envelope, Lane, LaneGeometry};
 
     use super::*;
+    use crate::color::{spectral_centroids, ColorMode};
+    use crate::error::Error;
+    use crate::layout::TimeLayout;
+    use crate::normalization::{Normalization, DEFAULT_SAMPLE_RATE};
+    use crate::sample::PcmSample;
+    use crate::style::{opaque, AmplitudeAxis, ViewSignalBuilder, WaveformStyle};
+
+    #[cfg(feature = "parallel")]
+    const PARALLEL_BAND_ROWS: usize = 32;
 
     pub struct ViewSignal {
-        image: ImageBuffer<Rgb<u8>, Vec<u8>>,
+        image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+        // What the waveform was drawn from, kept to redraw single columns.
+        // Images that are not waveforms (spectrograms) have none.
+        cache: Option<RenderCache>,
+    }
+
+    struct RenderCache {
+        lanes: Vec<Lane>,
+        wave_ratio: f32,
+        style: WaveformStyle,
+        // Columns left of it are drawn in `style.played_color`.
+        playhead: usize,
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum ChannelMode {
+        // Each channel in its own horizontal lane, first channel on top.
+        #[default]
+        Lanes,
+        // All channels drawn over each other across the full height.
+        Overlay,
+        // Channels averaged into a single mono wave.
+        MixDown,
     }
 
     impl ViewSignal {
-        pub fn new<T: Sample + Default + SizedSample + FromSample<T> + Debug + AddAssign>(
+        pub fn new<T: PcmSample>(
             sound: &[T],
             desired_size: [usize; 2],
             wave_color: [u8; 3],
             background_color: [u8; 3],
-        ) -> Self
-        where
-            f32: From<T>,
-        {
-            let height = desired_size[1] as f32;
-            let width = desired_size[0];
+        ) -> Self {
+            Self::try_new(sound, desired_size, wave_color, background_color).unwrap()
+        }
+
+        pub fn try_new<T: PcmSample>(
+            sound: &[T],
+            desired_size: [usize; 2],
+            wave_color: [u8; 3],
+            background_color: [u8; 3],
+        ) -> Result<Self, Error> {
+            Self::try_new_normalized(
+                sound,
+                Normalization::Peak,
+                desired_size,
+                wave_color,
+                background_color,
+            )
+        }
+
+        pub fn try_new_normalized<T: PcmSample>(
+            sound: &[T],
+            normalization: Normalization,
+            desired_size: [usize; 2],
+            wave_color: [u8; 3],
+            background_color: [u8; 3],
+        ) -> Result<Self, Error> {
+            Self::try_new_channels(
+                sound,
+                1,
+                ChannelMode::Overlay,
+                normalization,
+                desired_size,
+                wave_color,
+                background_color,
+            )
+        }
+
+        pub fn from_sample(
+            sample: &MySample,
+            mode: ChannelMode,
+            normalization: Normalization,
+            desired_size: [usize; 2],
+            wave_color: [u8; 3],
+            background_color: [u8; 3],
+        ) -> Self {
+            Self::try_from_sample(
+                sample,
+                mode,
+                normalization,
+                desired_size,
+                wave_color,
+                background_color,
+            )
+            .unwrap()
+        }
+
+        pub fn try_from_sample(
+            sample: &MySample,
+            mode: ChannelMode,
+            normalization: Normalization,
+            desired_size: [usize; 2],
+            wave_color: [u8; 3],
+            background_color: [u8; 3],
+        ) -> Result<Self, Error> {
+            let style = WaveformStyle {
+                size: desired_size,
+                wave_color: opaque(wave_color),
+                background_color: opaque(background_color),
+                channel_mode: mode,
+                normalization,
+                ..WaveformStyle::default()
+            };
+
+            Self::try_with_style(
+                &sample.samples,
+                sample.channels as usize,
+                sample.sample_rate,
+                &style,
+            )
+        }
+
+        // `sound` holds `channels` interleaved channels. Loudness normalization
+        // assumes `DEFAULT_SAMPLE_RATE` here; use `try_from_sample` when the
+        // rate is known.
+        pub fn try_new_channels<T: PcmSample>(
+            sound: &[T],
+            channels: usize,
+            mode: ChannelMode,
+            normalization: Normalization,
+            desired_size: [usize; 2],
+            wave_color: [u8; 3],
+            background_color: [u8; 3],
+        ) -> Result<Self, Error> {
+            let style = WaveformStyle {
+                size: desired_size,
+                wave_color: opaque(wave_color),
+                background_color: opaque(background_color),
+                channel_mode: mode,
+                normalization,
+                ..WaveformStyle::default()
+            };
+
+            Self::try_with_style(sound, channels, DEFAULT_SAMPLE_RATE, &style)
+        }
+
+        pub fn builder() -> ViewSignalBuilder {
+            ViewSignalBuilder::new()
+        }
+
+        pub fn try_with_style<T: PcmSample>(
+            sound: &[T],
+            channels: usize,
+            sample_rate: u32,
+            style: &WaveformStyle,
+        ) -> Result<Self, Error> {
+            image_len(style.size)?;
+            let wave_ratio = style.normalization.gain(sound, channels, sample_rate);
+
+            Self::render_channels(sound, channels, sample_rate, wave_ratio, style)
+        }
+
+        // Draws only the window `range` of the sound across the whole width.
+        // Column edges fall between samples and are interpolated, so the
+        // window does not snap to whole samples. The scale still comes from
+        // the whole sound, so that a scrolling view keeps one scale.
+        pub fn try_with_style_range<T: PcmSample>(
+            sound: &[T],
+            channels: usize,
+            sample_rate: u32,
+            range: Range<Duration>,
+            style: &WaveformStyle,
+        ) -> Result<Self, Error> {
+            image_len(style.size)?;
+            if range.end <= range.start {
+                return Err(Error::InvalidTimeRange {
+                    start: range.start,
+                    end: range.end,
+                });
+            }
+
+            let wave_ratio = style.normalization.gain(sound, channels, sample_rate);
+            let channels = channels.max(1);
+            let frames = sound.len() / channels;
+            let start = range.start.as_secs_f64() * sample_rate as f64;
+            let end = range.end.as_secs_f64() * sample_rate as f64;
+            let width = style.size[0];
 
-            let mut buffer = vec![255; desired_size[0] * height as usize * 3];
-            let channel_1 = vec![background_color[0]; width * height as usize];
-            let channel_2 = vec![background_color[1]; width * height as usize];
-            let channel_3 = vec![background_color[2]; width * height as usize];
+            let lane_count = match style.channel_mode {
+                ChannelMode::Lanes | ChannelMode::Overlay => channels,
+                ChannelMode::MixDown => 1,
+            };
 
-            buffer.chunks_mut(3).enumerate().for_each(|(i, dst)| {
-                dst[0] = channel_1[i];
-                dst[1] = channel_2[i];
-                dst[2] = channel_3[i];
-            });
+            let mut lanes: Vec<Lane> = (0..lane_count)
+                .map(|c| {
+                    let value = audio_process::lane_sample(sound, channels, style.channel_mode, c);
+                    Lane {
+                        area: audio_process::lane_area(style, channels, c),
+                        envelope: audio_process::range_envelope(frames, value, start, end, width),
+                        centroids: Vec::new(),
+                    }
+                })
+                .collect();
 
-            let mut dst_image = ImageBuffer::from_raw(width as u32, height as u32, buffer).unwrap();
+            if matches!(style.color_mode, ColorMode::SpectralCentroid) {
+                let window = start.floor() as usize..(end.ceil() as usize).min(frames);
+                for (c, lane) in lanes.iter_mut().enumerate() {
+                    let value = audio_process::lane_sample(sound, channels, style.channel_mode, c);
+                    lane.centroids = spectral_centroids(window.clone(), value, width, sample_rate);
+                }
+            }
+
+            Self::render_lanes(lanes, wave_ratio, style)
+        }
+
+        pub fn try_from_sample_range(
+            sample: &MySample,
+            range: Range<Duration>,
+            style: &WaveformStyle,
+        ) -> Result<Self, Error> {
+            Self::try_with_style_range(
+                &sample.samples,
+                sample.channels as usize,
+                sample.sample_rate,
+                range,
+                style,
+            )
+        }
+
+        fn render_channels<T: PcmSample>(
+            sound: &[T],
+            channels: usize,
+            sample_rate: u32,
+            wave_ratio: f32,
+            style: &WaveformStyle,
+        ) -> Result<Self, Error> {
+            let mut lanes = audio_process::lane_envelopes(sound, channels, style);
+
+            if matches!(style.color_mode, ColorMode::SpectralCentroid) {
+                let channels = channels.max(1);
+                let frames = sound.len() / channels;
+                for (c, lane) in lanes.iter_mut().enumerate() {
+                    let value = audio_process::lane_sample(sound, channels, style.channel_mode, c);
+                    lane.centroids =
+                        spectral_centroids(0..frames, value, style.size[0], sample_rate);
+                }
+            }
 
-            let color = Rgb(wave_color);
-            let highest: f32 = audio_process::wave_height_ratio::<T, f32>(sound);
-            let wave_ratio = 1.0 / highest;
+            Self::render_lanes(lanes, wave_ratio, style)
+        }
 
-            draw_wave(sound, wave_ratio, desired_size, &mut dst_image, wave_color);
+        pub(crate) fn render_lanes(
+            lanes: Vec<Lane>,
+            wave_ratio: f32,
+            style: &WaveformStyle,
+        ) -> Result<Self, Error> {
+            style.render_style.check_step()?;
+            let mut dst_image = blank_image(style.size, style.background_color)?;
+
+            #[cfg(not(feature = "parallel"))]
+            for lane in &lanes {
+                draw_envelope(
+                    lane,
+                    wave_ratio,
+                    &mut dst_image,
+                    style,
+                    0..style.size[0] as i64,
+                    0,
+                );
+            }
+
+            // Bands of rows are drawn independently; every pixel still sees
+            // the same spans in the same order.
+            #[cfg(feature = "parallel")]
+            {
+                use rayon::prelude::*;
+
+                let [width, height] = [dst_image.width(), dst_image.height()];
+                dst_image
+                    .par_chunks_mut(width as usize * 4 * PARALLEL_BAND_ROWS)
+                    .enumerate()
+                    .for_each(|(i, rows)| {
+                        let band_height = (rows.len() / (width as usize * 4)) as u32;
+                        let mut band = audio_process::Band {
+                            band: ImageBuffer::from_raw(width, band_height, rows)
+                                .expect("band holds whole rows"),
+                            top: (i * PARALLEL_BAND_ROWS) as u32,
+                            height,
+                        };
+                        for lane in &lanes {
+                            draw_envelope(lane, wave_ratio, &mut band, style, 0..width as i64, 0);
+                        }
+                    });
+            }
+
+            Ok(Self {
+                image: dst_image,
+                cache: Some(RenderCache {
+                    lanes,
+                    wave_ratio,
+                    style: style.clone(),
+                    playhead: 0,
+                }),
+            })
+        }
+
+        pub(crate) fn from_image(image: ImageBuffer<Rgba<u8>, Vec<u8>>) -> Self {
+            Self { image, cache: None }
+        }
+
+        pub(crate) fn image_mut(&mut self) -> &mut ImageBuffer<Rgba<u8>, Vec<u8>> {
+            &mut self.image
+        }
+
+        // Drawable area of each distinct lane, top to bottom.
+        pub(crate) fn lanes(&self) -> Vec<LaneGeometry> {
+            let Some(cache) = &self.cache else {
+                return Vec::new();
+            };
+
+            let mut geometries: Vec<LaneGeometry> = Vec::new();
+            for lane in &cache.lanes {
+                if let Some(geometry) = LaneGeometry::new(lane.area, cache.style.vertical_padding) {
+                    if !geometries.iter().any(|g| g.top == geometry.top) {
+                        geometries.push(geometry);
+                    }
+                }
+            }
+            geometries
+        }
 
-            Self { image: dst_image }
+        pub(crate) fn gain(&self) -> f32 {
+            self.cache
+                .as_ref()
+                .map_or(1.0, |cache| cache.wave_ratio * cache.style.amplitude_scale)
         }
+
+        pub(crate) fn amplitude_axis(&self) -> AmplitudeAxis {
+            self.cache
+                .as_ref()
+                .map_or(AmplitudeAxis::Linear, |cache| cache.style.amplitude_axis)
+        }
+
+        pub fn playhead(&self) -> usize {
+            self.cache.as_ref().map_or(0, |cache| cache.playhead)
+        }
+
+        // Moves the playhead to `column` and redraws only the columns that
+        // change color. Overlays drawn over those columns are lost.
+        pub fn set_playhead(&mut self, column: usize) {
+            let Some(cache) = &mut self.cache else {
+                return;
+            };
+            let column = column.min(self.image.width() as usize);
+            if column == cache.playhead {
+                return;
+            }
+
+            let columns = column.min(cache.playhead) as i64..column.max(cache.playhead) as i64;
+            cache.playhead = column;
+
+            let background = Rgba(cache.style.background_color);
+            for x in columns.clone() {
+                for y in 0..self.image.height() {
+                    self.image.put_pixel(x as u32, y, background);
+                }
+            }
+            for lane in &cache.lanes {
+                draw_envelope(
+                    lane,
+                    cache.wave_ratio,
+                    &mut self.image,
+                    &cache.style,
+                    columns.clone(),
+                    column,
+                );
+            }
+        }
+
+        pub fn set_playhead_time(&mut self, layout: &TimeLayout, time: Duration) {
+            let x = layout.x_at(time).round().max(0.0);
+            self.set_playhead(x as usize);
+        }
+
         pub fn save(&self, file_name: &str) {
-            self.image.save(file_name).unwrap();
+            self.try_save(file_name).unwrap();
         }
 
+        pub fn try_save(&self, file_name: &str) -> Result<(), Error> {
+            self.image.save(file_name)?;
+
+            Ok(())
+        }
+
+        // Encodes into `writer`, for outputs that are not files.
+        pub fn try_write_to<W: Write + Seek>(
+            &self,
+            writer: &mut W,
+            format: ImageFormat,
+        ) -> Result<(), Error> {
+            self.image.write_to(writer, format)?;
+
+            Ok(())
+        }
+
+        // Bytes are RGBA, row by row.
         pub fn convert<T>(&self, convert: impl FnOnce(&[u8], [usize; 2]) -> T) -> T {
             convert(
                 self.image.as_raw(),
@@ -71,115 +433,839 @@ mod visual_signal {
             self.image.as_raw()
         }
     }
+
+    // Bytes of an RGBA image of `desired_size`, or the error for a size that
+    // can't be drawn. Checked before anything the width of the image is
+    // allocated.
+    pub(crate) fn image_len(desired_size: [usize; 2]) -> Result<usize, Error> {
+        let [width, height] = desired_size;
+        if desired_size
+            .iter()
+            .any(|&d| d == 0 || d > u32::MAX as usize)
+        {
+            return Err(Error::InvalidDimensions { width, height });
+        }
+
+        width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or(Error::InvalidDimensions { width, height })
+    }
+
+    pub(crate) fn blank_image(
+        desired_size: [usize; 2],
+        background_color: [u8; 4],
+    ) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, Error> {
+        let invalid_dimensions = Error::InvalidDimensions {
+            width: desired_size[0],
+            height: desired_size[1],
+        };
+        let len = image_len(desired_size)?;
+
+        let mut buffer = vec![255; len];
+
+        buffer.chunks_mut(4).for_each(|dst| {
+            dst.copy_from_slice(&background_color);
+        });
+
+        ImageBuffer::from_raw(desired_size[0] as u32, desired_size[1] as u32, buffer)
+            .ok_or(invalid_dimensions)
+    }
 }
 
-mod audio_process {
-    use imageproc::{image::Rgb, pixelops::interpolate};
-    use std::{
-        fmt::{Debug, Display},
-        ops::{AddAssign, Div, Mul},
-    };
+pub(crate) mod audio_process {
+    use imageproc::image::Rgba;
+    use std::ops::Range;
 
-    use cpal::{FromSample, Sample, SizedSample};
     use imageproc::image::ImageBuffer;
 
-    use super::*;
-    use imageproc::drawing::draw_antialiased_line_segment_mut;
+    use super::ChannelMode;
+    use crate::color::{centroid_color, mix, ColorMode};
+    use crate::sample::PcmSample;
+    use crate::simd;
+    use crate::style::{RenderMode, RenderStyle, WaveformStyle};
 
-    pub fn find_highest_sample<T: FromSample<T> + SizedSample + Sample + AddAssign + Default>(
-        samples: &[T],
-    ) -> T {
-        let mut highest_value = T::default();
-        for sample in samples {
-            let s: T = T::from_sample(*sample);
-            if s > highest_value {
-                highest_value += s;
-            }
+    // Samples are converted to f32 in blocks of this many before going
+    // through the SIMD kernels. A multiple of `simd::SUM_LANES`.
+    const CONVERT_BLOCK: usize = 256;
+
+    fn for_each_block<T: PcmSample>(samples: &[T], mut f: impl FnMut(&[f32])) {
+        let mut block = [0.0f32; CONVERT_BLOCK];
+        for chunk in samples.chunks(CONVERT_BLOCK) {
+            T::convert(chunk, &mut block);
+            f(&block[..chunk.len()]);
         }
+    }
+
+    // Largest absolute amplitude, so that negative peaks count too.
+    pub fn find_highest_sample<T: PcmSample>(samples: &[T]) -> f32 {
+        let mut highest_value = 0.0f32;
+        for_each_block(samples, |block| {
+            highest_value = highest_value.max(simd::abs_peak(block));
+        });
 
         highest_value
     }
 
-    pub fn wave_height_ratio<
-        T: Sample + Default + SizedSample + FromSample<T> + Debug + AddAssign + Into<U>,
-        U,
-    >(
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct ColumnPeak {
+        pub min: f32,
+        pub max: f32,
+        pub rms: f32,
+    }
+
+    // Samples covered by pixel column `column`. Every column gets at least one
+    // sample when the sound is shorter than the image is wide.
+    pub fn column_range(column: usize, width: usize, sample_len: usize) -> Range<usize> {
+        let start = column * sample_len / width;
+        let end = ((column + 1) * sample_len / width)
+            .max(start + 1)
+            .min(sample_len);
+
+        start.min(end)..end
+    }
+
+    // Running min/max/sum of squares of one column. Squares are summed in
+    // the lanes of `simd::sum_squares`, so that adding samples one at a time
+    // or by slices gives the same result.
+    #[derive(Clone, Copy, Debug)]
+    pub struct PeakAccumulator {
+        min: f32,
+        max: f32,
+        sum_squares: [f64; simd::SUM_LANES],
+        count: usize,
+    }
+
+    impl Default for PeakAccumulator {
+        fn default() -> Self {
+            PeakAccumulator {
+                min: f32::MAX,
+                max: f32::MIN,
+                sum_squares: [0.0; simd::SUM_LANES],
+                count: 0,
+            }
+        }
+    }
+
+    impl PeakAccumulator {
+        pub fn add(&mut self, s: f32) {
+            self.min = self.min.min(s);
+            self.max = self.max.max(s);
+            self.sum_squares[self.count % simd::SUM_LANES] += (s as f64) * (s as f64);
+            self.count += 1;
+        }
+
+        pub fn add_slice(&mut self, samples: &[f32]) {
+            let misaligned = (simd::SUM_LANES - self.count % simd::SUM_LANES) % simd::SUM_LANES;
+            let (head, rest) = samples.split_at(misaligned.min(samples.len()));
+            for &s in head {
+                self.add(s);
+            }
+
+            (self.min, self.max) = simd::min_max(rest, self.min, self.max);
+            let consumed = simd::sum_squares(rest, &mut self.sum_squares);
+            self.count += consumed;
+            for &s in &rest[consumed..] {
+                self.sum_squares[self.count % simd::SUM_LANES] += (s as f64) * (s as f64);
+                self.count += 1;
+            }
+        }
+
+        pub fn peak(&self) -> ColumnPeak {
+            if self.count == 0 {
+                return ColumnPeak::default();
+            }
+
+            ColumnPeak {
+                min: self.min,
+                max: self.max,
+                rms: (simd::lane_total(&self.sum_squares) / self.count as f64).sqrt() as f32,
+            }
+        }
+    }
+
+    pub fn column_peak<T: PcmSample>(bucket: &[T]) -> ColumnPeak {
+        let mut accumulator = PeakAccumulator::default();
+        for_each_block(bucket, |block| accumulator.add_slice(block));
+
+        accumulator.peak()
+    }
+
+    // Streaming counterpart of `compute_envelope`: samples are pushed one by
+    // one and only the column in progress is kept. `sample_len` must be known
+    // up front since it decides the column boundaries.
+    pub struct EnvelopeAccumulator {
+        width: usize,
+        sample_len: usize,
+        position: usize,
+        column: usize,
+        current: PeakAccumulator,
+        envelope: Vec<ColumnPeak>,
+    }
+
+    impl EnvelopeAccumulator {
+        pub fn new(width: usize, sample_len: usize) -> Self {
+            EnvelopeAccumulator {
+                width,
+                sample_len,
+                position: 0,
+                column: 0,
+                current: PeakAccumulator::default(),
+                envelope: Vec::with_capacity(width),
+            }
+        }
+
+        pub fn push(&mut self, s: f32) {
+            if self.column >= self.width {
+                return;
+            }
+
+            self.current.add(s);
+            self.position += 1;
+
+            // Short sounds map one sample onto several columns.
+            while self.column < self.width
+                && column_range(self.column, self.width, self.sample_len).end <= self.position
+            {
+                self.envelope.push(self.current.peak());
+                self.column += 1;
+
+                if self.column < self.width
+                    && column_range(self.column, self.width, self.sample_len).start >= self.position
+                {
+                    self.current = PeakAccumulator::default();
+                }
+            }
+        }
+
+        pub fn finish(mut self) -> Vec<ColumnPeak> {
+            self.envelope.resize(self.width, ColumnPeak::default());
+            self.envelope
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    pub fn compute_envelope<T: PcmSample>(sound: &[T], width: usize) -> Vec<ColumnPeak> {
+        (0..width)
+            .map(|x| column_peak(&sound[column_range(x, width, sound.len())]))
+            .collect()
+    }
+
+    // Columns are independent, so splitting them across threads gives the
+    // same envelope.
+    #[cfg(feature = "parallel")]
+    pub fn compute_envelope<T: PcmSample>(sound: &[T], width: usize) -> Vec<ColumnPeak> {
+        use rayon::prelude::*;
+
+        (0..width)
+            .into_par_iter()
+            .map(|x| column_peak(&sound[column_range(x, width, sound.len())]))
+            .collect()
+    }
+
+    pub fn deinterleave<T: Copy>(sound: &[T], channels: usize) -> Vec<Vec<T>> {
+        let channels = channels.max(1);
+
+        (0..channels)
+            .map(|c| sound.iter().skip(c).step_by(channels).copied().collect())
+            .collect()
+    }
+
+    pub fn mix_down<T: PcmSample>(sound: &[T], channels: usize) -> Vec<f32> {
+        let channels = channels.max(1);
+
+        sound
+            .chunks(channels)
+            .map(|frame| {
+                let sum: f32 = frame.iter().map(|s| s.to_f32()).sum();
+                sum / frame.len() as f32
+            })
+            .collect()
+    }
+
+    // Envelope of the window [start, end), given in fractional frames, with
+    // `value(i)` the sample of frame i. Each column takes the frames strictly
+    // inside it plus the interpolated values at both of its edges.
+    pub fn range_envelope(
+        frames: usize,
+        value: impl Fn(usize) -> f32,
+        start: f64,
+        end: f64,
+        width: usize,
+    ) -> Vec<ColumnPeak> {
+        let step = (end - start) / width.max(1) as f64;
+        let value_at = |position: f64| -> Option<f32> {
+            if position < 0.0 || position > frames.saturating_sub(1) as f64 || frames == 0 {
+                return None;
+            }
+            let i = position.floor() as usize;
+            let fraction = (position - i as f64) as f32;
+            if fraction == 0.0 || i + 1 >= frames {
+                return Some(value(i));
+            }
+
+            Some(value(i) + (value(i + 1) - value(i)) * fraction)
+        };
+
+        (0..width)
+            .map(|x| {
+                let from = start + x as f64 * step;
+                let to = from + step;
+
+                let mut accumulator = PeakAccumulator::default();
+                if let Some(s) = value_at(from) {
+                    accumulator.add(s);
+                }
+                let first = (from.floor() + 1.0).max(0.0) as usize;
+                let last = if to.fract() == 0.0 { to } else { to.ceil() };
+                let last = last.clamp(0.0, frames as f64) as usize;
+                for i in first..last.max(first).min(frames) {
+                    accumulator.add(value(i));
+                }
+                if let Some(s) = value_at(to) {
+                    accumulator.add(s);
+                }
+
+                accumulator.peak()
+            })
+            .collect()
+    }
+
+    // Frame `i` of the signal drawn in lane `lane`.
+    pub fn lane_sample<T: PcmSample>(
         sound: &[T],
-    ) -> U {
-        let highest = audio_process::find_highest_sample::<T>(sound);
+        channels: usize,
+        mode: ChannelMode,
+        lane: usize,
+    ) -> impl Fn(usize) -> f32 + '_ {
+        move |i| match mode {
+            ChannelMode::Lanes | ChannelMode::Overlay => sound[i * channels + lane].to_f32(),
+            ChannelMode::MixDown => {
+                let frame = &sound[i * channels..(i + 1) * channels];
+                let sum: f32 = frame.iter().map(|s| s.to_f32()).sum();
+                sum / channels as f32
+            }
+        }
+    }
+
+    pub fn channel_envelopes<T: PcmSample>(
+        sound: &[T],
+        channels: usize,
+        width: usize,
+    ) -> Vec<Vec<ColumnPeak>> {
+        if channels <= 1 {
+            return vec![compute_envelope(sound, width)];
+        }
+
+        deinterleave(sound, channels)
+            .iter()
+            .map(|channel| compute_envelope(channel, width))
+            .collect()
+    }
 
-        highest.into()
+    // An envelope and the [top, height] band of the image it is centered in.
+    #[derive(Clone)]
+    pub struct Lane {
+        pub area: [usize; 2],
+        pub envelope: Vec<ColumnPeak>,
+        // Spectral centroid per column, only for `ColorMode::SpectralCentroid`.
+        pub centroids: Vec<f32>,
     }
-    pub fn draw_wave<T: Copy>(
+
+    pub fn lane_envelopes<T: PcmSample>(
         sound: &[T],
+        channels: usize,
+        style: &WaveformStyle,
+    ) -> Vec<Lane> {
+        let width = style.size[0];
+        let channels = channels.max(1);
+
+        let envelopes = match style.channel_mode {
+            ChannelMode::Lanes | ChannelMode::Overlay => channel_envelopes(sound, channels, width),
+            ChannelMode::MixDown => {
+                let mono = mix_down(sound, channels);
+                vec![compute_envelope::<f32>(&mono, width)]
+            }
+        };
+
+        envelopes
+            .into_iter()
+            .enumerate()
+            .map(|(c, envelope)| Lane {
+                area: lane_area(style, channels, c),
+                envelope,
+                centroids: Vec::new(),
+            })
+            .collect()
+    }
+
+    pub fn lane_area(style: &WaveformStyle, channels: usize, index: usize) -> [usize; 2] {
+        let height = style.size[1];
+
+        match style.channel_mode {
+            ChannelMode::Lanes => {
+                let lane_height = height / channels.max(1);
+                [index * lane_height, lane_height]
+            }
+            ChannelMode::Overlay | ChannelMode::MixDown => [0, height],
+        }
+    }
+
+    // Drawable part of a lane once the vertical padding is taken out.
+    #[derive(Clone, Copy, Debug)]
+    pub struct LaneGeometry {
+        pub top: f32,
+        pub bottom: f32,
+        pub center: f32,
+        pub half_height: f32,
+    }
+
+    impl LaneGeometry {
+        pub fn new(area: [usize; 2], vertical_padding: usize) -> Option<Self> {
+            let [lane_top, lane_height] = area;
+            let padding = vertical_padding.min(lane_height / 2);
+            let top = (lane_top + padding) as f32;
+            let height = (lane_height - 2 * padding) as f32;
+            if height <= 0.0 {
+                return None;
+            }
+
+            Some(LaneGeometry {
+                top,
+                bottom: top + height,
+                center: top + height / 2.0,
+                half_height: height / 2.0,
+            })
+        }
+
+        // Vertical extent of one column: at least `line_thickness` high and
+        // kept inside the lane.
+        pub fn span(&self, peak: &ColumnPeak, gain: f32, style: &WaveformStyle) -> (f32, f32) {
+            let (high, low) = match style.render_mode {
+                RenderMode::Peak => (peak.max, peak.min),
+                RenderMode::Rms => (peak.rms, -peak.rms),
+            };
+            let (high, low) = match style.render_style {
+                RenderStyle::Mirrored | RenderStyle::HalfWave => {
+                    let amplitude = high.abs().max(low.abs());
+                    (amplitude, -amplitude)
+                }
+                _ => (high, low),
+            };
+            let thickness = style.line_thickness.max(1) as f32;
+
+            let axis = style.amplitude_axis;
+            let (high, low) = (axis.scale(high * gain), axis.scale(low * gain));
+
+            let (mut top, mut bottom) = match style.render_style {
+                // Grows up from the bottom of the lane over its full height.
+                RenderStyle::HalfWave => (
+                    (self.bottom - 2.0 * self.half_height * high).clamp(self.top, self.bottom),
+                    self.bottom,
+                ),
+                _ => (
+                    (self.center - self.half_height * high).clamp(self.top, self.bottom),
+                    (self.center - self.half_height * low).clamp(self.top, self.bottom),
+                ),
+            };
+            if bottom - top < thickness {
+                let middle = (top + bottom) / 2.0;
+                top = middle - thickness / 2.0;
+                bottom = middle + thickness / 2.0;
+
+                let shift = (self.top - top).max(0.0) - (bottom - self.bottom).max(0.0);
+                top += shift;
+                bottom += shift;
+            }
+
+            (top, bottom)
+        }
+
+        // Row the zero level is drawn at.
+        pub fn baseline(&self, style: &WaveformStyle) -> f32 {
+            match style.render_style {
+                RenderStyle::HalfWave => self.bottom - 0.5,
+                _ => self.center,
+            }
+        }
+    }
+
+    // Draws the columns in `columns`, those left of `playhead` in the
+    // played color.
+    pub fn draw_envelope(
+        lane: &Lane,
         wave_ratio: f32,
-        desired_size: [usize; 2],
-        image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
-        wave_color: [u8; 3],
-    ) where
-        f32: From<T>,
-    {
-        let sample_len = sound.len();
-        let height = desired_size[1] as f32;
-        let wave_color = Rgb(wave_color);
-        for (i, s) in sound.iter().enumerate() {
-            let s: f32 = T::into(*s);
-            let x_pos_ratio = i as f32 / sample_len as f32;
-
-            let im_width: i32 = (x_pos_ratio * desired_size[0] as f32) as i32;
-
-            let s = s * wave_ratio;
-            let start = (im_width, height as i32 / 2);
-            if i % 2 == 0 {
-                let end = (im_width, height as i32 / 2 + (height / 2.0 * s) as i32);
-                draw_antialiased_line_segment_mut(image, start, end, wave_color, interpolate);
-            } else {
-                let end = (
-                    im_width,
-                    height as i32 / 2 - (height as f32 / 2.0 * s) as i32,
+        image: &mut impl Canvas,
+        style: &WaveformStyle,
+        columns: Range<i64>,
+        playhead: usize,
+    ) {
+        let Some(geometry) = LaneGeometry::new(lane.area, style.vertical_padding) else {
+            return;
+        };
+        let envelope = &lane.envelope;
+
+        let gain = wave_ratio * style.amplitude_scale;
+        let thickness = style.line_thickness.max(1);
+        let width = image.width() as f32;
+        let color = |x: i64, y: u32| {
+            if x < playhead as i64 {
+                return Rgba(style.played_color);
+            }
+            let column = (x.max(0) as usize).min(envelope.len().saturating_sub(1));
+            let peak = envelope.get(column).copied().unwrap_or_default();
+
+            Rgba(match style.color_mode {
+                ColorMode::Solid => style.wave_color,
+                ColorMode::VerticalGradient { top, bottom } => mix(
+                    top,
+                    bottom,
+                    (y as f32 + 0.5 - geometry.top) / (geometry.bottom - geometry.top),
+                ),
+                ColorMode::HorizontalGradient { start, end } => {
+                    mix(start, end, (x as f32 + 0.5) / width)
+                }
+                ColorMode::Amplitude { quiet, loud } => {
+                    let amplitude = peak.max.abs().max(peak.min.abs()) * gain;
+                    mix(quiet, loud, style.amplitude_axis.scale(amplitude))
+                }
+                ColorMode::Loudness {
+                    quiet,
+                    loud,
+                    floor_db,
+                } => {
+                    let db = 20.0 * (peak.rms * gain).max(1e-10).log10();
+                    mix(quiet, loud, 1.0 - db / floor_db.min(-f32::EPSILON))
+                }
+                ColorMode::SpectralCentroid => match lane.centroids.get(column) {
+                    Some(&centroid) => centroid_color(centroid, style.wave_color[3]),
+                    None => style.wave_color,
+                },
+            })
+        };
+
+        if style.show_baseline {
+            let baseline = geometry.baseline(style);
+            for x in columns.clone() {
+                fill_span(
+                    image,
+                    x,
+                    baseline - 0.5,
+                    baseline + 0.5,
+                    |y| color(x, y),
+                    false,
                 );
-                draw_antialiased_line_segment_mut(image, start, end, wave_color, interpolate);
             }
         }
+
+        // Shapes wider than a column also cover their neighbours.
+        let reach = match style.render_style {
+            RenderStyle::Bars { width, gap, .. } => width + gap,
+            RenderStyle::Dots { radius, gap } => 2 * radius + gap,
+            _ => 0,
+        } as i64
+            + thickness as i64;
+        let first = (columns.start - reach).max(0) as usize;
+        let last = ((columns.end + reach).max(0) as usize).min(envelope.len());
+
+        let span = |x: usize| geometry.span(&envelope[x], gain, style);
+        let mut paint = |column: i64, top: f32, bottom: f32| {
+            if columns.contains(&column) {
+                fill_span(
+                    image,
+                    column,
+                    top,
+                    bottom,
+                    |y| color(column, y),
+                    style.antialiasing,
+                );
+            }
+        };
+
+        match style.render_style {
+            RenderStyle::Filled | RenderStyle::Mirrored | RenderStyle::HalfWave => {
+                for x in first..last {
+                    let (top, bottom) = span(x);
+
+                    let left = x as i64 - (thickness as i64 - 1) / 2;
+                    for column in left..left + thickness as i64 {
+                        paint(column, top, bottom);
+                    }
+                }
+            }
+            RenderStyle::Line => {
+                // Each column holds the part of the outline between the
+                // midpoints to its neighbours.
+                let half = thickness as f32 / 2.0;
+                for x in first..last {
+                    let (top, bottom) = span(x);
+                    let previous = span(x.saturating_sub(1));
+                    let next = span((x + 1).min(envelope.len() - 1));
+
+                    for (edge, before, after) in
+                        [(top, previous.0, next.0), (bottom, previous.1, next.1)]
+                    {
+                        let (before, after) = ((edge + before) / 2.0, (edge + after) / 2.0);
+                        let from = edge.min(before).min(after);
+                        let to = edge.max(before).max(after);
+                        paint(x as i64, from - half, to + half);
+                    }
+                }
+            }
+            RenderStyle::Bars { width, gap, radius } => {
+                let width = width.max(1);
+                let step = width + gap;
+                for bar in first / step..last.div_ceil(step) {
+                    let start = bar * step;
+                    let end = (start + width).min(envelope.len());
+                    let (top, bottom) = (start..end).map(span).fold(
+                        (f32::INFINITY, f32::NEG_INFINITY),
+                        |(t, b), (top, bottom)| (t.min(top), b.max(bottom)),
+                    );
+                    let radius = (radius as f32)
+                        .min((end - start) as f32 / 2.0)
+                        .min((bottom - top) / 2.0);
+
+                    for column in start..end {
+                        // How far the column reaches into a rounded corner.
+                        let inside = (column - start).min(end - 1 - column) as f32 + 0.5;
+                        let dx = (radius - inside).max(0.0);
+                        let inset = radius - (radius * radius - dx * dx).max(0.0).sqrt();
+                        paint(column as i64, top + inset, bottom - inset);
+                    }
+                }
+            }
+            RenderStyle::Dots { radius, gap } => {
+                let step = 2 * radius.max(1) + gap;
+                let radius = radius.max(1) as f32;
+                for dot in first / step..last.div_ceil(step) {
+                    let center = dot * step + radius as usize;
+                    if center >= envelope.len() {
+                        break;
+                    }
+                    let (top, bottom) = span(center);
+                    let rows = if bottom - top < 4.0 * radius {
+                        vec![(top + bottom) / 2.0]
+                    } else {
+                        vec![top + radius, bottom - radius]
+                    };
+
+                    let x = center as f32 + 0.5;
+                    for column in (x - radius).floor() as i64..(x + radius).ceil() as i64 {
+                        let dx = column as f32 + 0.5 - x;
+                        let chord = (radius * radius - dx * dx).max(0.0).sqrt();
+                        for &y in &rows {
+                            paint(column, y - chord, y + chord);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Something to draw on with whole-image coordinates: the image itself,
+    // or a band of its rows when rows are drawn in parallel.
+    pub trait Canvas {
+        fn width(&self) -> u32;
+        fn height(&self) -> u32;
+        fn rows(&self) -> Range<u32>;
+        fn pixel_mut(&mut self, x: u32, y: u32) -> &mut Rgba<u8>;
+    }
+
+    impl Canvas for ImageBuffer<Rgba<u8>, Vec<u8>> {
+        fn width(&self) -> u32 {
+            self.width()
+        }
+
+        fn height(&self) -> u32 {
+            self.height()
+        }
+
+        fn rows(&self) -> Range<u32> {
+            0..self.height()
+        }
+
+        fn pixel_mut(&mut self, x: u32, y: u32) -> &mut Rgba<u8> {
+            self.get_pixel_mut(x, y)
+        }
+    }
+
+    // Rows `top..top + band.height()` of an image `height` rows high.
+    #[cfg(feature = "parallel")]
+    pub struct Band<'a> {
+        pub band: ImageBuffer<Rgba<u8>, &'a mut [u8]>,
+        pub top: u32,
+        pub height: u32,
+    }
+
+    #[cfg(feature = "parallel")]
+    impl Canvas for Band<'_> {
+        fn width(&self) -> u32 {
+            self.band.width()
+        }
+
+        fn height(&self) -> u32 {
+            self.height
+        }
+
+        fn rows(&self) -> Range<u32> {
+            self.top..self.top + self.band.height()
+        }
+
+        fn pixel_mut(&mut self, x: u32, y: u32) -> &mut Rgba<u8> {
+            self.band.get_pixel_mut(x, y - self.top)
+        }
+    }
+
+    // Fills column `x` between the rows `top` and `bottom`. With antialiasing,
+    // rows only partly covered by the span are blended by their coverage.
+    pub fn fill_span(
+        image: &mut impl Canvas,
+        x: i64,
+        top: f32,
+        bottom: f32,
+        color: impl Fn(u32) -> Rgba<u8>,
+        antialiasing: bool,
+    ) {
+        if x < 0 || x >= image.width() as i64 {
+            return;
+        }
+        let height = image.height();
+        let rows = image.rows();
+        let top = top.max(0.0);
+        let bottom = bottom.min(height as f32);
+        if top >= bottom {
+            return;
+        }
+
+        if !antialiasing {
+            let first = top.round() as u32;
+            let last = (bottom.round() as u32).max(first + 1).min(height);
+            for y in first.max(rows.start)..last.min(rows.end) {
+                blend_over(image.pixel_mut(x as u32, y), color(y), 1.0);
+            }
+            return;
+        }
+
+        let first = top.floor() as u32;
+        let last = (bottom.ceil() as u32).min(height);
+        for y in first.max(rows.start)..last.min(rows.end) {
+            let coverage = (bottom.min(y as f32 + 1.0) - top.max(y as f32)).clamp(0.0, 1.0);
+            blend_over(image.pixel_mut(x as u32, y), color(y), coverage);
+        }
+    }
+
+    // Source-over compositing of `color` onto `pixel`, with the color's alpha
+    // scaled by `coverage`.
+    pub fn blend_over(pixel: &mut Rgba<u8>, color: Rgba<u8>, coverage: f32) {
+        let src_alpha = color.0[3] as f32 / 255.0 * coverage;
+        let dst_alpha = pixel.0[3] as f32 / 255.0;
+        let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+        if out_alpha <= 0.0 {
+            *pixel = Rgba([0, 0, 0, 0]);
+            return;
+        }
+
+        for c in 0..3 {
+            let src = color.0[c] as f32 * src_alpha;
+            let dst = pixel.0[c] as f32 * dst_alpha * (1.0 - src_alpha);
+            pixel.0[c] = ((src + dst) / out_alpha).round() as u8;
+        }
+        pixel.0[3] = (out_alpha * 255.0).round() as u8;
     }
 }
 
 mod audio_open {
 
     use std::fs::File;
-    use std::io::BufReader;
+    use std::io::{BufReader, Read, Seek};
     use std::time::Duration;
 
     use rodio::{source::Source, Decoder};
 
+    use crate::error::Error;
+    use crate::layout::TimeLayout;
+    use crate::metadata::{frames_to_duration, AudioMetadata};
+    use crate::peaks::WaveformPeaks;
+    use crate::stream::SharedReader;
+
     pub struct MySample {
         pub samples: Vec<f32>,
         pub duration: Duration,
+        pub sample_rate: u32,
+        pub channels: u16,
+        pub frames: usize,
+        pub metadata: AudioMetadata,
     }
 
     impl MySample {
         pub fn new(file_path: &str) -> Self {
-            let file = BufReader::new(File::open(file_path).unwrap());
-            let source = Decoder::new(file).unwrap();
+            Self::try_new(file_path).unwrap()
+        }
+
+        pub fn try_new(file_path: &str) -> Result<Self, Error> {
+            Self::try_from_reader(BufReader::new(File::open(file_path)?))
+        }
+
+        pub fn try_from_reader<R: Read + Seek + Send + Sync + 'static>(
+            reader: R,
+        ) -> Result<Self, Error> {
+            let reader = SharedReader::new(reader)?;
+            let metadata = AudioMetadata::probe(&reader);
+
+            let source = Decoder::new(reader)?;
 
             let sample_rate = source.sample_rate();
             let channels = source.channels();
 
             let mut samples: Vec<f32> = vec![];
 
-            for (i, s) in source.convert_samples::<f32>().step_by(1).enumerate() {
+            for s in source.convert_samples::<f32>() {
                 samples.push(s)
             }
 
-            let duration = (samples.len() / sample_rate as usize) / channels as usize;
-            let duration_secs = std::time::Duration::from_secs(duration as u64);
-            MySample {
+            let frames = samples.len() / channels.max(1) as usize;
+            Ok(MySample {
                 samples,
-                duration: duration_secs,
-            }
+                duration: frames_to_duration(frames, sample_rate),
+                sample_rate,
+                channels,
+                frames,
+                metadata,
+            })
         }
-        pub fn convert_duration_to_width(&self) -> usize {
-            self.samples.len() / 100
+
+        // Samples of a single channel, taken out of the interleaved buffer.
+        pub fn channel(&self, index: usize) -> Vec<f32> {
+            self.samples
+                .iter()
+                .skip(index)
+                .step_by(self.channels.max(1) as usize)
+                .copied()
+                .collect()
+        }
+
+        // Min/max peaks at `levels` resolutions, starting at `samples_per_pixel`.
+        pub fn peaks(&self, samples_per_pixel: usize, levels: usize) -> WaveformPeaks {
+            WaveformPeaks::from_interleaved(
+                &self.samples,
+                self.channels as usize,
+                self.sample_rate,
+                samples_per_pixel,
+                levels,
+            )
+        }
+
+        pub fn deinterleaved(&self) -> Vec<Vec<f32>> {
+            super::audio_process::deinterleave(&self.samples, self.channels as usize)
+        }
+
+        pub fn convert_duration_to_width(&self, layout: &TimeLayout) -> usize {
+            layout.width_for_frames(self.frames as f64)
+        }
+
+        pub fn layout(&self, pixels_per_second: f64) -> TimeLayout {
+            TimeLayout::from_pixels_per_second(pixels_per_second, self.sample_rate)
         }
     }
 }
@@ -187,6 +1273,7 @@ mod audio_open {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::simd::test::random_samples;
 
     #[test]
     fn main() {
@@ -202,4 +1289,562 @@ mod test {
         );
         view.save("/home/camille/Documents/rust/sound-wave-image/ressources/test_22.png");
     }
+
+    #[test]
+    fn envelope_keeps_min_and_max_of_each_column() {
+        let sound: Vec<f32> = vec![0.5, -0.25, 1.0, -1.0, 0.0, 0.0];
+        let envelope = audio_process::compute_envelope(&sound, 3);
+
+        assert_eq!(envelope.len(), 3);
+        assert_eq!((envelope[0].min, envelope[0].max), (-0.25, 0.5));
+        assert_eq!((envelope[1].min, envelope[1].max), (-1.0, 1.0));
+        assert_eq!(envelope[1].rms, 1.0);
+        assert_eq!(envelope[2], audio_process::ColumnPeak::default());
+    }
+
+    #[test]
+    fn envelope_covers_every_column_of_short_sounds() {
+        let sound: Vec<f32> = vec![0.5, -0.5];
+        let envelope = audio_process::compute_envelope(&sound, 4);
+
+        assert_eq!(envelope.len(), 4);
+        assert!(envelope
+            .iter()
+            .all(|peak| peak.max != 0.0 || peak.min != 0.0));
+    }
+
+    #[test]
+    fn zero_sized_image_is_an_error() {
+        let sound: Vec<f32> = vec![0.5, -0.5];
+        let view = ViewSignal::try_new(&sound, [0, 100], [255, 0, 0], [0, 0, 0]);
+
+        assert!(matches!(
+            view,
+            Err(crate::Error::InvalidDimensions {
+                width: 0,
+                height: 100
+            })
+        ));
+
+        // Rejected before the envelope allocates its columns.
+        let huge = u32::MAX as usize;
+        let style = crate::WaveformStyle {
+            size: [huge, huge],
+            ..Default::default()
+        };
+        let range = std::time::Duration::ZERO..std::time::Duration::from_secs(1);
+        for view in [
+            ViewSignal::try_with_style(&sound, 1, 44_100, &style),
+            ViewSignal::try_with_style_range(&sound, 1, 44_100, range, &style),
+        ] {
+            assert!(matches!(
+                view,
+                Err(crate::Error::InvalidDimensions { width, .. }) if width == huge
+            ));
+        }
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let sample = MySample::try_new("this/file/does/not/exist.mp3");
+
+        assert!(matches!(sample, Err(crate::Error::Io(_))));
+    }
+
+    #[test]
+    fn deinterleave_splits_channels() {
+        let sound: Vec<f32> = vec![0.1, -0.1, 0.2, -0.2, 0.3, -0.3];
+        let channels = audio_process::deinterleave(&sound, 2);
+
+        assert_eq!(channels, vec![vec![0.1, 0.2, 0.3], vec![-0.1, -0.2, -0.3]]);
+        assert_eq!(audio_process::mix_down(&sound, 2), vec![0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn highest_sample_counts_negative_peaks() {
+        let sound: Vec<f32> = vec![0.25, -0.75, 0.5];
+
+        assert_eq!(audio_process::find_highest_sample(&sound), 0.75);
+    }
+
+    #[test]
+    fn silence_keeps_a_unit_gain() {
+        let silence: Vec<f32> = vec![0.0; 4410];
+
+        for normalization in [
+            crate::Normalization::Peak,
+            crate::Normalization::Rms { target: 0.5 },
+            crate::Normalization::Loudness { target_lufs: -14.0 },
+        ] {
+            assert_eq!(normalization.gain(&silence, 1, 44_100), 1.0);
+        }
+    }
+
+    #[test]
+    fn shared_normalization_uses_the_loudest_file() {
+        let quiet: Vec<f32> = vec![0.1, -0.2];
+        let loud: Vec<f32> = vec![0.5, -0.4];
+
+        let shared = crate::Normalization::Peak
+            .shared([(quiet.as_slice(), 1, 44_100), (loud.as_slice(), 1, 44_100)]);
+
+        assert_eq!(shared, crate::Normalization::Gain(2.0));
+    }
+
+    #[test]
+    fn vertical_padding_keeps_rows_clear() {
+        let sound: Vec<f32> = vec![1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0];
+        let view = ViewSignal::builder()
+            .size([4, 10])
+            .vertical_padding(3)
+            .wave_color([255, 255, 255])
+            .background_color([0, 0, 0])
+            .antialiasing(false)
+            .build(&sound)
+            .unwrap();
+
+        let black = [0, 0, 0, 255];
+        let rows: Vec<Vec<&[u8]>> = view
+            .as_bytes()
+            .chunks(4 * 4)
+            .map(|row| row.chunks(4).collect())
+            .collect();
+        assert!(rows[..3].iter().flatten().all(|px| *px == black));
+        assert!(rows[3].iter().all(|px| *px == [255, 255, 255, 255]));
+        assert!(rows[7..].iter().flatten().all(|px| *px == black));
+    }
+
+    #[test]
+    fn transparent_background_keeps_zero_alpha() {
+        let sound: Vec<f32> = vec![1.0, -1.0, 1.0, -1.0];
+        let view = ViewSignal::builder()
+            .size([2, 8])
+            .wave_rgba([255, 0, 0, 128])
+            .background_rgba([0, 0, 0, 0])
+            .vertical_padding(2)
+            .antialiasing(false)
+            .build(&sound)
+            .unwrap();
+
+        let pixels: Vec<&[u8]> = view.as_bytes().chunks(4).collect();
+        assert_eq!(pixels[0], [0, 0, 0, 0]);
+        assert_eq!(pixels[2 * 2], [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn svg_outline_follows_the_envelope() {
+        let sound: Vec<f32> = vec![1.0, -1.0, 0.5, -0.5];
+        let style = crate::WaveformStyle {
+            size: [2, 10],
+            ..Default::default()
+        };
+
+        let svg =
+            crate::SvgSignal::try_with_style(&sound, 1, 4, &style, Default::default()).unwrap();
+
+        assert!(svg.as_str().starts_with("<svg "));
+        assert!(svg
+            .as_str()
+            .contains(r#"d="M0.5 0L1.5 2.5 1.5 7.5 0.5 10Z""#));
+
+        let half_wave = crate::WaveformStyle {
+            render_style: crate::RenderStyle::HalfWave,
+            show_baseline: true,
+            ..style.clone()
+        };
+        let svg =
+            crate::SvgSignal::try_with_style(&sound, 1, 4, &half_wave, Default::default()).unwrap();
+        assert!(svg.as_str().contains(r#"y1="9.5""#));
+
+        let huge = crate::WaveformStyle {
+            size: [usize::MAX, 10],
+            ..style.clone()
+        };
+        assert!(matches!(
+            crate::SvgSignal::try_with_style(&sound, 1, 4, &huge, Default::default()),
+            Err(crate::Error::InvalidDimensions { .. })
+        ));
+
+        for style in [
+            crate::WaveformStyle {
+                render_style: crate::RenderStyle::Line,
+                ..style.clone()
+            },
+            crate::WaveformStyle {
+                render_style: crate::RenderStyle::Dots { radius: 1, gap: 0 },
+                ..style.clone()
+            },
+            crate::WaveformStyle {
+                color_mode: crate::ColorMode::HorizontalGradient {
+                    start: [0; 4],
+                    end: [255; 4],
+                },
+                ..style
+            },
+        ] {
+            assert!(matches!(
+                crate::SvgSignal::try_with_style(&sound, 1, 4, &style, Default::default()),
+                Err(crate::Error::InvalidSettings(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn streaming_matches_the_in_memory_render() {
+        let sound: Vec<f32> = (0..2 * 5000)
+            .map(|i| ((i as f32) * 0.37).sin() * (i % 7) as f32 / 7.0)
+            .collect();
+
+        for (width, channel_mode, normalization) in [
+            (64, ChannelMode::Lanes, crate::Normalization::Peak),
+            (
+                64,
+                ChannelMode::MixDown,
+                crate::Normalization::Rms { target: 0.4 },
+            ),
+            (
+                9000,
+                ChannelMode::Overlay,
+                crate::Normalization::Loudness { target_lufs: -14.0 },
+            ),
+        ] {
+            let style = crate::WaveformStyle {
+                size: [width, 40],
+                channel_mode,
+                normalization,
+                ..Default::default()
+            };
+
+            let in_memory = ViewSignal::try_with_style(&sound, 2, 8000, &style).unwrap();
+
+            let mut stream = crate::WaveformStream::new(&style, 2, 8000, sound.len() / 2);
+            stream.push_interleaved(&sound);
+            let streamed = stream.finish().unwrap();
+
+            assert!(in_memory.as_bytes() == streamed.as_bytes());
+        }
+
+        let huge = crate::WaveformStyle {
+            size: [usize::MAX, 40],
+            ..Default::default()
+        };
+        assert!(matches!(
+            crate::WaveformStream::try_new(&huge, 2, 8000, sound.len() / 2),
+            Err(crate::Error::InvalidDimensions { .. })
+        ));
+    }
+
+    #[test]
+    fn range_envelope_interpolates_column_edges() {
+        let sound: Vec<f32> = vec![0.0, 0.1, 0.2, 0.3];
+        let value = |i: usize| sound[i];
+
+        let envelope = audio_process::range_envelope(4, value, 1.0, 3.0, 2);
+        assert_eq!((envelope[0].min, envelope[0].max), (0.1, 0.2));
+        assert_eq!((envelope[1].min, envelope[1].max), (0.2, 0.3));
+
+        let envelope = audio_process::range_envelope(4, value, 1.0, 2.0, 4);
+        assert!((envelope[0].max - 0.125).abs() < 1e-6);
+        assert!((envelope[3].min - 0.175).abs() < 1e-6);
+    }
+
+    #[test]
+    fn spectrogram_puts_a_tone_in_its_row() {
+        let sample_rate = 8000;
+        let sound: Vec<f32> = (0..2048)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / sample_rate as f32).sin())
+            .collect();
+        let style = crate::SpectrogramStyle {
+            size: [2, 8],
+            fft_size: 256,
+            hop: 256,
+            frequency_scale: crate::FrequencyScale::Linear,
+            colormap: crate::Colormap::Grayscale,
+            db_range: [-60.0, 0.0],
+            ..Default::default()
+        };
+
+        let view = ViewSignal::try_spectrogram(&sound, 1, sample_rate, &style).unwrap();
+
+        // 1 kHz sits in the row covering 1000..1500 Hz, two rows above the bottom.
+        let rows: Vec<&[u8]> = view.as_bytes().chunks(2 * 4).collect();
+        assert!(rows[5][0] > 200);
+        assert!(rows[0][0] < 50);
+    }
+
+    #[test]
+    fn duration_keeps_the_fraction_of_a_second() {
+        let duration = crate::metadata::frames_to_duration(66_150, 44_100);
+
+        assert_eq!(duration, std::time::Duration::from_millis(1500));
+        assert_eq!(
+            crate::metadata::sniff_container(b"RIFF\0\0\0\0WAVEfmt "),
+            Some("wav")
+        );
+    }
+
+    #[test]
+    fn layout_maps_pixels_back_to_time() {
+        let layout = crate::TimeLayout::from_pixels_per_second(100.0, 44_100)
+            .with_start(std::time::Duration::from_secs(2));
+
+        assert_eq!(layout.width(std::time::Duration::from_millis(1500)), 150);
+        assert_eq!(layout.time_at(50.0), std::time::Duration::from_millis(2500));
+        assert!((layout.x_at(std::time::Duration::from_secs(3)) - 100.0).abs() < 1e-9);
+        assert_eq!(layout.frame_at(1.0), 88_200 + 441);
+
+        let stopped = crate::TimeLayout::from_pixels_per_second(0.0, 44_100);
+        assert_eq!(stopped.time_at(10.0), std::time::Duration::MAX);
+        let range = std::time::Duration::ZERO..std::time::Duration::from_secs(1);
+        let silent = crate::TimeLayout::fit(range, 100, 0);
+        assert_eq!(silent.visible_range(100).end, std::time::Duration::MAX);
+    }
+
+    #[test]
+    fn decibel_grid_marks_the_source_level() {
+        let sound: Vec<f32> = vec![0.5, -0.5, 0.5, -0.5];
+        let style = crate::WaveformStyle {
+            size: [4, 101],
+            wave_color: [0, 0, 0, 0],
+            normalization: crate::Normalization::Gain(1.0),
+            ..Default::default()
+        };
+        let mut view = ViewSignal::try_with_style(&sound, 1, 4, &style).unwrap();
+        let annotations = crate::Annotations {
+            time_axis: false,
+            amplitude_grid: crate::AmplitudeGrid::Decibel,
+            labels: false,
+            font_size: 8.0,
+            grid_color: [255, 0, 0, 255],
+            ..Default::default()
+        };
+        let layout = crate::TimeLayout::fit(
+            std::time::Duration::ZERO..std::time::Duration::from_secs(1),
+            4,
+            4,
+        );
+        view.annotate(&layout, &annotations);
+
+        // -6 dB is close to half the lane, 25 rows above the center.
+        let red_rows: Vec<usize> = view
+            .as_bytes()
+            .chunks(4 * 4)
+            .enumerate()
+            .filter(|(_, row)| row[0] == 255 && row[1] == 0)
+            .map(|(y, _)| y)
+            .collect();
+        assert!(red_rows.contains(&25));
+        assert!(red_rows.contains(&0));
+    }
+
+    #[test]
+    fn moving_the_playhead_matches_a_played_render() {
+        let sound: Vec<f32> = (0..64).map(|i| ((i * 7) % 13) as f32 / 6.5 - 1.0).collect();
+        let style = crate::WaveformStyle {
+            size: [16, 20],
+            line_thickness: 3,
+            show_baseline: true,
+            played_color: [255, 0, 0, 255],
+            ..Default::default()
+        };
+        let played = crate::WaveformStyle {
+            wave_color: style.played_color,
+            ..style.clone()
+        };
+
+        let mut view = ViewSignal::try_with_style(&sound, 1, 64, &style).unwrap();
+        view.set_playhead(5);
+        view.set_playhead(16);
+        let expected = ViewSignal::try_with_style(&sound, 1, 64, &played).unwrap();
+
+        assert_eq!(view.as_bytes(), expected.as_bytes());
+
+        view.set_playhead(0);
+        let unplayed = ViewSignal::try_with_style(&sound, 1, 64, &style).unwrap();
+        assert_eq!(view.as_bytes(), unplayed.as_bytes());
+    }
+
+    #[test]
+    fn bars_leave_gaps_and_half_wave_grows_from_the_bottom() {
+        let sound: Vec<f32> = [0.5, -0.5].repeat(8);
+        let mut style = crate::WaveformStyle {
+            size: [8, 20],
+            antialiasing: false,
+            normalization: crate::Normalization::Gain(1.0),
+            render_style: crate::RenderStyle::Bars {
+                width: 2,
+                gap: 1,
+                radius: 0,
+            },
+            ..Default::default()
+        };
+        let is_black =
+            |view: &ViewSignal, x: usize, y: usize| view.as_bytes()[(y * 8 + x) * 4] == 0;
+
+        let bars = ViewSignal::try_with_style(&sound, 1, 16, &style).unwrap();
+        assert!(is_black(&bars, 0, 10) && is_black(&bars, 1, 10));
+        assert!(!is_black(&bars, 2, 10));
+        assert!(is_black(&bars, 3, 10));
+
+        style.render_style = crate::RenderStyle::HalfWave;
+        let half = ViewSignal::try_with_style(&sound, 1, 16, &style).unwrap();
+        assert!(is_black(&half, 0, 19) && is_black(&half, 0, 10));
+        assert!(!is_black(&half, 0, 9));
+
+        // Loaded from a preset, so too wide a step is an error, not a panic.
+        for render_style in [
+            crate::RenderStyle::Bars {
+                width: usize::MAX,
+                gap: 1,
+                radius: 0,
+            },
+            crate::RenderStyle::Dots {
+                radius: usize::MAX / 2 + 1,
+                gap: 0,
+            },
+        ] {
+            style.render_style = render_style;
+            assert!(matches!(
+                ViewSignal::try_with_style(&sound, 1, 16, &style),
+                Err(crate::Error::InvalidSettings(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn spectral_centroid_colors_bass_red_and_highs_blue() {
+        let sample_rate = 32_000;
+        let tone = |frequency: f32, i: usize| {
+            (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin()
+        };
+        let sound: Vec<f32> = (0..4096)
+            .map(|i| tone(100.0, i))
+            .chain((0..4096).map(|i| tone(8000.0, i)))
+            .collect();
+        let style = crate::WaveformStyle {
+            size: [2, 10],
+            color_mode: crate::ColorMode::SpectralCentroid,
+            ..Default::default()
+        };
+
+        let view = ViewSignal::try_with_style(&sound, 1, sample_rate, &style).unwrap();
+
+        let bass = &view.as_bytes()[5 * 2 * 4..][..4];
+        let highs = &view.as_bytes()[(5 * 2 + 1) * 4..][..4];
+        assert!(bass[0] > 200 && bass[2] < 50);
+        assert!(highs[2] > 200 && highs[0] < 50);
+    }
+
+    #[test]
+    fn decibel_axis_lifts_quiet_passages() {
+        let axis = crate::AmplitudeAxis::Decibel { floor_db: -60.0 };
+
+        assert!((axis.scale(0.001) - 0.0).abs() < 1e-6);
+        assert!((axis.scale(-0.1) + 2.0 / 3.0).abs() < 1e-5);
+        assert!((axis.unscale(axis.scale(0.25)) - 0.25).abs() < 1e-5);
+
+        let hybrid = crate::AmplitudeAxis::Hybrid {
+            floor_db: -60.0,
+            blend: 0.5,
+        };
+        assert!((hybrid.scale(0.1) - (0.05 + 1.0 / 3.0)).abs() < 1e-5);
+        assert!((hybrid.unscale(hybrid.scale(0.1)) - 0.1).abs() < 1e-4);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_bands_match_a_single_pass() {
+        let sound: Vec<f32> = (0..4000)
+            .map(|i| ((i * 37) % 101) as f32 / 50.0 - 1.0)
+            .collect();
+        let style = crate::WaveformStyle {
+            size: [300, 150],
+            line_thickness: 2,
+            channel_mode: ChannelMode::Overlay,
+            ..Default::default()
+        };
+
+        let view = ViewSignal::try_with_style(&sound, 2, 44_100, &style).unwrap();
+
+        let wave_ratio = style.normalization.gain(&sound, 2, 44_100);
+        let mut image = blank_image(style.size, style.background_color).unwrap();
+        for lane in audio_process::lane_envelopes(&sound, 2, &style) {
+            audio_process::draw_envelope(&lane, wave_ratio, &mut image, &style, 0..300, 0);
+        }
+        assert_eq!(view.as_bytes(), image.as_raw().as_slice());
+    }
+
+    #[test]
+    fn column_peak_matches_sample_by_sample_accumulation() {
+        let mut seed = 7;
+        for len in [0, 1, 3, 4, 5, 255, 256, 257, 1001] {
+            let samples = random_samples(&mut seed, len);
+
+            let mut accumulator = audio_process::PeakAccumulator::default();
+            for &s in &samples {
+                accumulator.add(s);
+            }
+
+            assert_eq!(audio_process::column_peak(&samples), accumulator.peak());
+        }
+    }
+
+    #[test]
+    fn integer_pcm_renders_like_normalized_floats() {
+        let style = crate::WaveformStyle {
+            size: [64, 32],
+            normalization: crate::Normalization::Gain(1.0),
+            ..crate::WaveformStyle::default()
+        };
+        let pcm: Vec<i16> = (0..4000)
+            .map(|i| ((i as f32 * 0.01).sin() * 16384.0) as i16)
+            .collect();
+        let floats: Vec<f32> = pcm.iter().map(|&s| s as f32 / 32768.0).collect();
+        let wide: Vec<i32> = pcm.iter().map(|&s| (s as i32) << 16).collect();
+
+        let expected = ViewSignal::try_with_style(&floats, 1, 44_100, &style).unwrap();
+        let from_i16 = ViewSignal::try_with_style(&pcm, 1, 44_100, &style).unwrap();
+        let from_i32 = ViewSignal::try_with_style(&wide, 1, 44_100, &style).unwrap();
+
+        assert_eq!(from_i16.as_bytes(), expected.as_bytes());
+        assert_eq!(from_i32.as_bytes(), expected.as_bytes());
+        assert_eq!(audio_process::find_highest_sample(&[0u8, 255u8]), 1.0);
+    }
+
+    #[test]
+    fn bulk_conversions_match_sample_by_sample() {
+        use crate::{PackedI24, PcmSample};
+
+        let mut seed = 7;
+        for len in [0, 1, 7, 8, 9, 1003] {
+            let noise = random_samples(&mut seed, len);
+            let pcm: Vec<i16> = noise.iter().map(|&s| (s * 32767.0) as i16).collect();
+            let bytes: Vec<u8> = noise
+                .iter()
+                .flat_map(|&s| {
+                    let [low, middle, high, _] = ((s * 8_388_607.0) as i32).to_le_bytes();
+                    [low, middle, high]
+                })
+                .collect();
+            let packed = PackedI24::from_bytes(&bytes);
+
+            let mut converted = vec![0.0; len];
+            i16::convert(&pcm, &mut converted);
+            let expected: Vec<f32> = pcm.iter().map(|s| s.to_f32()).collect();
+            assert_eq!(converted, expected);
+
+            PackedI24::convert(packed, &mut converted);
+            let expected: Vec<f32> = packed
+                .iter()
+                .map(|s| s.to_i32() as f32 / 8_388_608.0)
+                .collect();
+            assert_eq!(converted, expected);
+        }
+
+        // The plain constructors take any `PcmSample`, not only cpal's.
+        let packed = PackedI24::from_bytes(&[0, 0, 64, 0, 0, 192]);
+        let view = ViewSignal::try_new(packed, [2, 4], [255; 3], [0; 3]).unwrap();
+        let expected = ViewSignal::try_new(&[0.5f32, -0.5], [2, 4], [255; 3], [0; 3]).unwrap();
+        assert!(view.as_bytes() == expected.as_bytes());
+    }
 }
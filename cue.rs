@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::metadata::frames_to_duration;
+use crate::overlay::{Marker, Region};
+
+// CD frames per second, the unit of CUE sheet INDEX times.
+const CUE_SHEET_FRAMES: u32 = 75;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CuePoint {
+    pub id: u32,
+    pub time: Duration,
+    // Set when the source gives the cue an extent (WAV `ltxt`, or the gap to
+    // the next track of a CUE sheet).
+    pub length: Option<Duration>,
+    pub label: Option<String>,
+}
+
+impl CuePoint {
+    // Cue points of a WAV file: positions from the `cue ` chunk, names and
+    // lengths from the `LIST`/`adtl` chunk.
+    pub fn from_wav<R: Read + Seek>(mut reader: R) -> Result<Vec<Self>, Error> {
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(Error::CueFormat("not a RIFF/WAVE file".into()));
+        }
+
+        let mut sample_rate = None;
+        let mut positions: Vec<(u32, u32)> = Vec::new();
+        let mut labels: HashMap<u32, String> = HashMap::new();
+        let mut lengths: HashMap<u32, u32> = HashMap::new();
+
+        loop {
+            let mut chunk = [0; 8];
+            match reader.read_exact(&mut chunk) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            let size = u32_at(&chunk, 4) as u64;
+            let padding = (size & 1) as i64;
+
+            if !matches!(&chunk[0..4], b"fmt " | b"cue " | b"LIST") {
+                reader.seek(SeekFrom::Current(size as i64 + padding))?;
+                continue;
+            }
+
+            let mut data = Vec::new();
+            (&mut reader).take(size).read_to_end(&mut data)?;
+            if (data.len() as u64) < size {
+                return Err(Error::CueFormat("truncated chunk".into()));
+            }
+            reader.seek(SeekFrom::Current(padding))?;
+
+            match &chunk[0..4] {
+                b"fmt " if data.len() >= 8 => sample_rate = Some(u32_at(&data, 4)),
+                b"cue " if data.len() >= 4 => {
+                    let count = u32_at(&data, 0) as usize;
+                    if data.len() < 4 + count * 24 {
+                        return Err(Error::CueFormat(format!("{count} cue points do not fit")));
+                    }
+                    positions.extend(
+                        data[4..4 + count * 24]
+                            .chunks_exact(24)
+                            .map(|point| (u32_at(point, 0), u32_at(point, 20))),
+                    );
+                }
+                b"LIST" if data.starts_with(b"adtl") => {
+                    read_associated_data(&data[4..], &mut labels, &mut lengths);
+                }
+                _ => {}
+            }
+        }
+
+        let Some(sample_rate) = sample_rate else {
+            return Err(Error::CueFormat("missing fmt chunk".into()));
+        };
+
+        let mut cues: Vec<CuePoint> = positions
+            .into_iter()
+            .map(|(id, frame)| CuePoint {
+                id,
+                time: frames_to_duration(frame as usize, sample_rate),
+                length: lengths
+                    .get(&id)
+                    .map(|&frames| frames_to_duration(frames as usize, sample_rate)),
+                label: labels.remove(&id),
+            })
+            .collect();
+        cues.sort_by_key(|cue| cue.time);
+
+        Ok(cues)
+    }
+
+    // One cue per TRACK, at its INDEX 01, labelled "performer - title".
+    pub fn from_cue_sheet(text: &str) -> Result<Vec<Self>, Error> {
+        let mut cues: Vec<CuePoint> = Vec::new();
+        let mut track: Option<SheetTrack> = None;
+
+        for line in text.lines() {
+            let line = line.trim();
+            let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match command.to_ascii_uppercase().as_str() {
+                "TRACK" => {
+                    if let Some(track) = track.take() {
+                        cues.push(track.into_cue()?);
+                    }
+                    let number = rest.split_whitespace().next().unwrap_or_default();
+                    let id = number
+                        .parse()
+                        .map_err(|_| Error::CueFormat(format!("invalid track number {number}")))?;
+                    track = Some(SheetTrack {
+                        id,
+                        ..Default::default()
+                    });
+                }
+                "TITLE" => {
+                    if let Some(track) = &mut track {
+                        track.title = Some(unquote(rest));
+                    }
+                }
+                "PERFORMER" => {
+                    if let Some(track) = &mut track {
+                        track.performer = Some(unquote(rest));
+                    }
+                }
+                "INDEX" => {
+                    let mut parts = rest.split_whitespace();
+                    if let (Some(track), Some("01"), Some(time)) =
+                        (&mut track, parts.next(), parts.next())
+                    {
+                        track.start = Some(parse_cue_time(time)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(track) = track {
+            cues.push(track.into_cue()?);
+        }
+
+        cues.sort_by_key(|cue| cue.time);
+        for i in 1..cues.len() {
+            cues[i - 1].length = Some(cues[i].time - cues[i - 1].time);
+        }
+
+        Ok(cues)
+    }
+
+    pub fn marker(&self, color: [u8; 3]) -> Marker {
+        Marker {
+            time: self.time,
+            label: self.label.clone(),
+            color,
+            opacity: 1.0,
+        }
+    }
+
+    // Cues without a length have no region.
+    pub fn region(&self, color: [u8; 3], opacity: f32) -> Option<Region> {
+        Some(Region {
+            start: self.time,
+            end: self.time + self.length?,
+            label: self.label.clone(),
+            color,
+            opacity,
+        })
+    }
+}
+
+#[derive(Default)]
+struct SheetTrack {
+    id: u32,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Option<Duration>,
+}
+
+impl SheetTrack {
+    fn into_cue(self) -> Result<CuePoint, Error> {
+        let Some(time) = self.start else {
+            return Err(Error::CueFormat(format!(
+                "track {} has no INDEX 01",
+                self.id
+            )));
+        };
+        let label = match (self.performer, self.title) {
+            (Some(performer), Some(title)) => Some(format!("{performer} - {title}")),
+            (performer, title) => title.or(performer),
+        };
+
+        Ok(CuePoint {
+            id: self.id,
+            time,
+            length: None,
+            label,
+        })
+    }
+}
+
+// `labl` and `note` give a cue its text, `ltxt` its length in frames.
+fn read_associated_data(
+    mut data: &[u8],
+    labels: &mut HashMap<u32, String>,
+    lengths: &mut HashMap<u32, u32>,
+) {
+    while data.len() >= 12 {
+        let size = u32_at(data, 4) as usize;
+        let Some(body) = data.get(8..8 + size).filter(|body| body.len() >= 4) else {
+            return;
+        };
+        let id = u32_at(body, 0);
+
+        match &data[0..4] {
+            b"labl" => {
+                labels.insert(id, zero_terminated(&body[4..]));
+            }
+            b"note" => {
+                labels
+                    .entry(id)
+                    .or_insert_with(|| zero_terminated(&body[4..]));
+            }
+            b"ltxt" if body.len() >= 8 => {
+                lengths.insert(id, u32_at(body, 4));
+            }
+            _ => {}
+        }
+
+        data = data.get(8 + size + (size & 1)..).unwrap_or_default();
+    }
+}
+
+fn parse_cue_time(time: &str) -> Result<Duration, Error> {
+    let invalid = || Error::CueFormat(format!("invalid time {time}"));
+    let parts: Vec<u32> = time
+        .split(':')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let [minutes, seconds, frames] = parts[..] else {
+        return Err(invalid());
+    };
+
+    let frames = minutes
+        .checked_mul(60)
+        .and_then(|s| s.checked_add(seconds))
+        .and_then(|s| s.checked_mul(CUE_SHEET_FRAMES))
+        .and_then(|f| f.checked_add(frames))
+        .ok_or_else(invalid)?;
+    Ok(frames_to_duration(frames as usize, CUE_SHEET_FRAMES))
+}
+
+fn unquote(text: &str) -> String {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+        .to_string()
+}
+
+fn zero_terminated(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wav_cue_chunk_gives_labelled_points() {
+        let mut wav: Vec<u8> = Vec::new();
+        let mut chunk = |id: &[u8], body: &[u8]| {
+            wav.extend_from_slice(id);
+            wav.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            wav.extend_from_slice(body);
+        };
+        let mut fmt = vec![1, 0, 1, 0];
+        fmt.extend_from_slice(&8000u32.to_le_bytes());
+        let mut cue = 1u32.to_le_bytes().to_vec();
+        for value in [7u32, 0, u32::from_le_bytes(*b"data"), 0, 0, 12_000] {
+            cue.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut list = b"adtllabl".to_vec();
+        list.extend_from_slice(&8u32.to_le_bytes());
+        list.extend_from_slice(&7u32.to_le_bytes());
+        list.extend_from_slice(b"Bip\0");
+
+        chunk(b"RIFF", b"WAVE");
+        chunk(b"fmt ", &fmt);
+        chunk(b"data", &[0, 0]);
+        chunk(b"cue ", &cue);
+        chunk(b"LIST", &list);
+
+        let cues = CuePoint::from_wav(std::io::Cursor::new(wav)).unwrap();
+
+        assert_eq!(cues.len(), 1);
+        assert_eq!(cues[0].time, Duration::from_millis(1500));
+        assert_eq!(cues[0].label.as_deref(), Some("Bip"));
+    }
+
+    #[test]
+    fn cue_sheet_tracks_become_regions() {
+        let sheet = r#"
+            FILE "album.wav" WAVE
+              TRACK 01 AUDIO
+                TITLE "Intro"
+                INDEX 01 00:00:00
+              TRACK 02 AUDIO
+                TITLE "Song"
+                PERFORMER "Band"
+                INDEX 00 01:29:00
+                INDEX 01 01:30:37
+        "#;
+
+        let cues = CuePoint::from_cue_sheet(sheet).unwrap();
+
+        assert_eq!(cues.len(), 2);
+        assert_eq!(cues[1].label.as_deref(), Some("Band - Song"));
+        assert_eq!(cues[1].time.as_millis(), 90_493);
+        let region = cues[0].region([255, 0, 0], 0.3).unwrap();
+        assert_eq!(region.end, cues[1].time);
+        assert!(cues[1].region([255, 0, 0], 0.3).is_none());
+
+        let overflowing = "TRACK 01 AUDIO\nINDEX 01 99999999:00:00";
+        assert!(matches!(
+            CuePoint::from_cue_sheet(overflowing),
+            Err(Error::CueFormat(_))
+        ));
+    }
+}
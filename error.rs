@@ -0,0 +1,79 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use imageproc::image::ImageError;
+use rodio::decoder::DecoderError;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(DecoderError),
+    InvalidDimensions { width: usize, height: usize },
+    InvalidTimeRange { start: Duration, end: Duration },
+    InvalidSettings(String),
+    Encode(ImageError),
+    PeakFormat(String),
+    CueFormat(String),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "i/o error: {e}"),
+            Error::Decode(e) => write!(f, "could not decode audio: {e}"),
+            Error::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Error::InvalidTimeRange { start, end } => {
+                write!(f, "invalid time range {start:?}..{end:?}")
+            }
+            Error::InvalidSettings(reason) => write!(f, "invalid settings: {reason}"),
+            Error::Encode(e) => write!(f, "could not encode image: {e}"),
+            Error::PeakFormat(reason) => write!(f, "invalid peak data: {reason}"),
+            Error::CueFormat(reason) => write!(f, "invalid cue data: {reason}"),
+            Error::Json(e) => write!(f, "invalid peak json: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::InvalidDimensions { .. }
+            | Error::InvalidTimeRange { .. }
+            | Error::InvalidSettings(_)
+            | Error::PeakFormat(_)
+            | Error::CueFormat(_) => None,
+            Error::Encode(e) => Some(e),
+            Error::Json(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<DecoderError> for Error {
+    fn from(e: DecoderError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(e: ImageError) -> Self {
+        Error::Encode(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
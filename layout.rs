@@ -0,0 +1,83 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+// Maps between time and horizontal pixels. `start` is the time drawn at
+// x = 0, so a layout also describes a view rendered from a time range.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimeLayout {
+    pub sample_rate: u32,
+    pub samples_per_pixel: f64,
+    pub start: Duration,
+}
+
+impl TimeLayout {
+    pub fn from_samples_per_pixel(samples_per_pixel: f64, sample_rate: u32) -> Self {
+        TimeLayout {
+            sample_rate,
+            samples_per_pixel: samples_per_pixel.max(f64::MIN_POSITIVE),
+            start: Duration::ZERO,
+        }
+    }
+
+    pub fn from_pixels_per_second(pixels_per_second: f64, sample_rate: u32) -> Self {
+        Self::from_samples_per_pixel(sample_rate as f64 / pixels_per_second, sample_rate)
+    }
+
+    // Layout of an image `width` pixels wide showing `range`.
+    pub fn fit(range: Range<Duration>, width: usize, sample_rate: u32) -> Self {
+        let seconds = range.end.saturating_sub(range.start).as_secs_f64();
+        let samples_per_pixel = seconds * sample_rate as f64 / width.max(1) as f64;
+
+        Self::from_samples_per_pixel(samples_per_pixel, sample_rate).with_start(range.start)
+    }
+
+    pub fn with_start(mut self, start: Duration) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn pixels_per_second(&self) -> f64 {
+        self.sample_rate as f64 / self.samples_per_pixel
+    }
+
+    // Columns needed to draw `duration`, the last one possibly partial.
+    pub fn width(&self, duration: Duration) -> usize {
+        self.width_for_frames(duration.as_secs_f64() * self.sample_rate as f64)
+    }
+
+    pub fn width_for_frames(&self, frames: f64) -> usize {
+        let columns = frames / self.samples_per_pixel;
+        let rounded = columns.round();
+
+        // 44100 frames at 100 px/s must stay 441 columns despite float error.
+        if (columns - rounded).abs() < 1e-6 {
+            rounded as usize
+        } else {
+            columns.ceil() as usize
+        }
+    }
+
+    pub fn x_at(&self, time: Duration) -> f64 {
+        let offset = time.as_secs_f64() - self.start.as_secs_f64();
+        offset * self.pixels_per_second()
+    }
+
+    // Time under `x`; positions left of the view clamp to its start. A
+    // degenerate layout (zero pixels per second or sample rate) gives
+    // `Duration::MAX` past its start instead of panicking.
+    pub fn time_at(&self, x: f64) -> Duration {
+        let seconds = self.start.as_secs_f64() + x / self.pixels_per_second();
+        Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or(Duration::MAX)
+    }
+
+    pub fn frame_at(&self, x: f64) -> usize {
+        let frame = self.start.as_secs_f64() * self.sample_rate as f64 + x * self.samples_per_pixel;
+        frame.max(0.0) as usize
+    }
+
+    pub fn visible_range(&self, width: usize) -> Range<Duration> {
+        self.start..self.time_at(width as f64)
+    }
+}
@@ -1,3 +1,36 @@
+mod annotate;
+mod batch;
+mod color;
 mod core;
+mod cue;
+mod error;
+mod layout;
+mod metadata;
+mod normalization;
+mod overlay;
+mod pcm;
+mod peaks;
+mod sample;
+mod simd;
+mod spectrogram;
+mod stream;
+mod style;
+mod svg;
 
-pub use core::ViewSignal;
+pub use annotate::{AmplitudeGrid, Annotations};
+pub use batch::{BatchEntry, BatchOptions, BatchReport, BatchStatus, OutputFormat};
+pub use color::ColorMode;
+pub use core::{ChannelMode, MySample, ViewSignal};
+pub use cue::CuePoint;
+pub use error::Error;
+pub use layout::TimeLayout;
+pub use metadata::AudioMetadata;
+pub use normalization::{LevelStats, Normalization};
+pub use overlay::{Marker, Region};
+pub use pcm::{Endianness, PcmDescriptor, PcmFormat, PcmLayout, PcmSource};
+pub use peaks::{PeakLevel, WaveformPeaks};
+pub use sample::{PackedI24, PcmSample};
+pub use spectrogram::{Colormap, FrequencyScale, SpectrogramStyle, WindowFunction};
+pub use stream::WaveformStream;
+pub use style::{AmplitudeAxis, RenderMode, RenderStyle, ViewSignalBuilder, WaveformStyle};
+pub use svg::{SvgOptions, SvgSignal};
@@ -0,0 +1,558 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use imageproc::image::ImageFormat;
+use sound_wave_image::{
+    BatchOptions, BatchStatus, ChannelMode, Error, MySample, OutputFormat, RenderStyle, SvgOptions,
+    SvgSignal, ViewSignal, WaveformStyle,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "sound-wave-image",
+    version,
+    about = "Renders waveform images of audio files",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(
+        required = true,
+        help = "Audio files or glob patterns such as 'music/*.mp3'"
+    )]
+    inputs: Vec<String>,
+    #[arg(
+        short,
+        long,
+        help = "Output file, directory when there are several inputs, or - for stdout [default: next to the input]"
+    )]
+    output: Option<PathBuf>,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        help = "Image format [default: from the output extension, else png]"
+    )]
+    format: Option<Format>,
+    #[command(flatten)]
+    style: StyleArgs,
+    #[arg(long, value_parser = parse_seconds, help = "Start of the rendered range in seconds")]
+    start: Option<Duration>,
+    #[arg(long, value_parser = parse_seconds, help = "End of the rendered range in seconds")]
+    end: Option<Duration>,
+    #[arg(short, long, help = "No progress on stderr")]
+    quiet: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Renders every audio file of a directory tree")]
+    Batch(BatchArgs),
+}
+
+#[derive(Args)]
+struct BatchArgs {
+    #[arg(help = "Directory searched for audio files")]
+    input: PathBuf,
+    #[arg(help = "Directory the images are written to, mirroring the input tree")]
+    output: PathBuf,
+    #[arg(short, long, value_enum, default_value = "png")]
+    format: Format,
+    #[arg(
+        short,
+        long,
+        default_value_t = 0,
+        help = "Files rendered at the same time [default: one per core]",
+        hide_default_value = true
+    )]
+    jobs: usize,
+    #[arg(long, help = "Only the files directly in the input directory")]
+    no_recursive: bool,
+    #[arg(long, help = "Renders outputs that are newer than their source too")]
+    force: bool,
+    #[arg(long, help = "Writes the JSON summary here instead of stdout")]
+    report: Option<PathBuf>,
+    #[command(flatten)]
+    style: StyleArgs,
+    #[arg(short, long, help = "No progress on stderr")]
+    quiet: bool,
+}
+
+#[derive(Args)]
+struct StyleArgs {
+    #[arg(short = 'W', long, help = "Width in pixels")]
+    width: Option<usize>,
+    #[arg(short = 'H', long, help = "Height in pixels")]
+    height: Option<usize>,
+    #[arg(long, value_parser = parse_color, help = "Wave color as RRGGBB or RRGGBBAA")]
+    wave_color: Option<[u8; 4]>,
+    #[arg(long, value_parser = parse_color, help = "Background as RRGGBB, RRGGBBAA or 'transparent'")]
+    background: Option<[u8; 4]>,
+    #[arg(long, value_enum)]
+    style: Option<Style>,
+    #[arg(long, value_enum)]
+    channels: Option<Channels>,
+    #[arg(
+        long,
+        help = "WaveformStyle preset in JSON; the other options override it"
+    )]
+    preset: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Png,
+    Bmp,
+    Tiff,
+    Svg,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Format::Png),
+            "bmp" => Some(Format::Bmp),
+            "tif" | "tiff" => Some(Format::Tiff),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Bmp => "bmp",
+            Format::Tiff => "tiff",
+            Format::Svg => "svg",
+        }
+    }
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Png => OutputFormat::Png,
+            Format::Bmp => OutputFormat::Bmp,
+            Format::Tiff => OutputFormat::Tiff,
+            Format::Svg => OutputFormat::Svg,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Style {
+    Filled,
+    Mirrored,
+    Line,
+    HalfWave,
+    Bars,
+    Dots,
+}
+
+impl From<Style> for RenderStyle {
+    fn from(style: Style) -> Self {
+        match style {
+            Style::Filled => RenderStyle::Filled,
+            Style::Mirrored => RenderStyle::Mirrored,
+            Style::Line => RenderStyle::Line,
+            Style::HalfWave => RenderStyle::HalfWave,
+            Style::Bars => RenderStyle::Bars {
+                width: 3,
+                gap: 1,
+                radius: 1,
+            },
+            Style::Dots => RenderStyle::Dots { radius: 1, gap: 2 },
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Channels {
+    Lanes,
+    Overlay,
+    Mix,
+}
+
+impl From<Channels> for ChannelMode {
+    fn from(channels: Channels) -> Self {
+        match channels {
+            Channels::Lanes => ChannelMode::Lanes,
+            Channels::Overlay => ChannelMode::Overlay,
+            Channels::Mix => ChannelMode::MixDown,
+        }
+    }
+}
+
+// Exit codes, so scripts can tell bad arguments from bad files. With
+// several inputs the first failure wins and the others are still rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Failure {
+    // Same code clap exits with on a bad command line.
+    Usage = 2,
+    NoInput = 3,
+    Decode = 4,
+    Render = 5,
+    // A batch went through, but some files in its report failed.
+    Incomplete = 6,
+}
+
+impl From<Failure> for ExitCode {
+    fn from(failure: Failure) -> Self {
+        ExitCode::from(failure as u8)
+    }
+}
+
+enum Output {
+    Stdout,
+    File(PathBuf),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match &cli.command {
+        Some(Command::Batch(args)) => batch(args),
+        None => run(&cli),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => failure.into(),
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), Failure> {
+    let style = style(&cli.style)?;
+    let range = range(cli)?;
+    let inputs = expand_inputs(&cli.inputs)?;
+    if inputs.is_empty() {
+        eprintln!("error: no input file found");
+        return Err(Failure::NoInput);
+    }
+
+    let outputs = outputs(cli, &inputs)?;
+    let mut result = Ok(());
+    for (index, (input, (output, format))) in inputs.iter().zip(outputs).enumerate() {
+        if !cli.quiet {
+            let target = match &output {
+                Output::Stdout => "<stdout>".to_string(),
+                Output::File(path) => path.display().to_string(),
+            };
+            eprintln!(
+                "[{}/{}] {} -> {target}",
+                index + 1,
+                inputs.len(),
+                input.display()
+            );
+        }
+
+        let rendered = render(input, &style, range.clone(), format).and_then(|bytes| {
+            write_output(&output, &bytes).map_err(|e| (Failure::Render, e.into()))
+        });
+        if let Err((failure, e)) = rendered {
+            eprintln!("error: {}: {e}", input.display());
+            result = result.and(Err(failure));
+        }
+    }
+
+    result
+}
+
+fn batch(args: &BatchArgs) -> Result<(), Failure> {
+    let options = BatchOptions {
+        style: style(&args.style)?,
+        format: args.format.into(),
+        workers: args.jobs,
+        recursive: !args.no_recursive,
+        force: args.force,
+    };
+
+    let report = options
+        .run_with_progress(&args.input, &args.output, |entry, done, total| {
+            if args.quiet {
+                return;
+            }
+            let status = match entry.status {
+                BatchStatus::Rendered => "rendered",
+                BatchStatus::Skipped => "up to date",
+                BatchStatus::Failed => "failed",
+            };
+            eprintln!("[{done}/{total}] {} {status}", entry.source.display());
+        })
+        .map_err(|e| {
+            eprintln!("error: {}: {e}", args.input.display());
+            Failure::NoInput
+        })?;
+    if report.files.is_empty() {
+        eprintln!("error: no audio file in {}", args.input.display());
+        return Err(Failure::NoInput);
+    }
+
+    let output = match &args.report {
+        Some(path) => Output::File(path.clone()),
+        None => Output::Stdout,
+    };
+    let written = report
+        .to_json()
+        .and_then(|json| Ok(write_output(&output, json.as_bytes())?));
+    if let Err(e) = written {
+        eprintln!("error: report: {e}");
+        return Err(Failure::Render);
+    }
+
+    if report.failed > 0 {
+        return Err(Failure::Incomplete);
+    }
+    Ok(())
+}
+
+fn style(cli: &StyleArgs) -> Result<WaveformStyle, Failure> {
+    let mut style = match &cli.preset {
+        Some(path) => {
+            let preset = fs::read_to_string(path)
+                .map_err(Error::from)
+                .and_then(|json| serde_json::from_str(&json).map_err(Error::from));
+            preset.map_err(|e| {
+                eprintln!("error: preset {}: {e}", path.display());
+                Failure::Usage
+            })?
+        }
+        None => WaveformStyle::default(),
+    };
+
+    if let Some(width) = cli.width {
+        style.size[0] = width;
+    }
+    if let Some(height) = cli.height {
+        style.size[1] = height;
+    }
+    if let Some(color) = cli.wave_color {
+        style.wave_color = color;
+    }
+    if let Some(color) = cli.background {
+        style.background_color = color;
+    }
+    if let Some(render_style) = cli.style {
+        style.render_style = render_style.into();
+    }
+    if let Some(channels) = cli.channels {
+        style.channel_mode = channels.into();
+    }
+
+    Ok(style)
+}
+
+fn range(cli: &Cli) -> Result<Option<Range<Duration>>, Failure> {
+    if cli.start.is_none() && cli.end.is_none() {
+        return Ok(None);
+    }
+
+    let start = cli.start.unwrap_or(Duration::ZERO);
+    let end = cli.end.unwrap_or(Duration::MAX);
+    if end <= start {
+        eprintln!("error: {}", Error::InvalidTimeRange { start, end });
+        return Err(Failure::Usage);
+    }
+
+    Ok(Some(start..end))
+}
+
+// Patterns are expanded here so that quoting them works the same on every
+// shell. A path that exists is taken as is, even with glob characters.
+fn expand_inputs(patterns: &[String]) -> Result<Vec<PathBuf>, Failure> {
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        if Path::new(pattern).is_file() {
+            inputs.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let paths = glob::glob(pattern).map_err(|e| {
+            eprintln!("error: invalid pattern {pattern}: {e}");
+            Failure::Usage
+        })?;
+        let before = inputs.len();
+        inputs.extend(paths.flatten().filter(|path| path.is_file()));
+        if inputs.len() == before {
+            eprintln!("warning: nothing matches {pattern}");
+        }
+    }
+
+    Ok(inputs)
+}
+
+fn outputs(cli: &Cli, inputs: &[PathBuf]) -> Result<Vec<(Output, Format)>, Failure> {
+    let format = |path: Option<&Path>| {
+        cli.format
+            .or_else(|| path.and_then(Format::from_path))
+            .unwrap_or(Format::Png)
+    };
+    let next_to = |input: &Path, directory: Option<&Path>| {
+        let format = format(None);
+        let name = input.with_extension(format.extension());
+        let path = match directory {
+            Some(directory) => directory.join(name.file_name().unwrap_or_default()),
+            None => name,
+        };
+        (Output::File(path), format)
+    };
+
+    match cli.output.as_deref() {
+        Some(path) if path == Path::new("-") => {
+            if inputs.len() > 1 {
+                eprintln!("error: only one input can be written to stdout");
+                return Err(Failure::Usage);
+            }
+            Ok(vec![(Output::Stdout, format(None))])
+        }
+        Some(path) if inputs.len() > 1 || path.is_dir() => {
+            fs::create_dir_all(path).map_err(|e| {
+                eprintln!("error: {}: {e}", path.display());
+                Failure::Render
+            })?;
+            Ok(inputs
+                .iter()
+                .map(|input| next_to(input, Some(path)))
+                .collect())
+        }
+        Some(path) => Ok(vec![(Output::File(path.to_path_buf()), format(Some(path)))]),
+        None => Ok(inputs.iter().map(|input| next_to(input, None)).collect()),
+    }
+}
+
+fn render(
+    input: &Path,
+    style: &WaveformStyle,
+    range: Option<Range<Duration>>,
+    format: Format,
+) -> Result<Vec<u8>, (Failure, Error)> {
+    let sample = File::open(input)
+        .map_err(Error::from)
+        .and_then(|file| MySample::try_from_reader(BufReader::new(file)))
+        .map_err(|e| (Failure::Decode, e))?;
+
+    if let Some(range) = &range {
+        if range.start >= sample.duration {
+            let reason = format!(
+                "--start {:?} is past the end of the input ({:?})",
+                range.start, sample.duration
+            );
+            return Err((Failure::Usage, Error::InvalidSettings(reason)));
+        }
+    }
+    let range = range.map(|range| range.start..range.end.min(sample.duration));
+    let rendered = match format {
+        Format::Svg => svg(&sample, style, range).map(String::into_bytes),
+        Format::Png | Format::Bmp | Format::Tiff => {
+            let view = match range {
+                Some(range) => ViewSignal::try_from_sample_range(&sample, range, style),
+                None => ViewSignal::try_with_style(
+                    &sample.samples,
+                    sample.channels as usize,
+                    sample.sample_rate,
+                    style,
+                ),
+            };
+            view.and_then(|view| {
+                let image_format = match format {
+                    Format::Bmp => ImageFormat::Bmp,
+                    Format::Tiff => ImageFormat::Tiff,
+                    _ => ImageFormat::Png,
+                };
+                let mut bytes = Cursor::new(Vec::new());
+                view.try_write_to(&mut bytes, image_format)?;
+                Ok(bytes.into_inner())
+            })
+        }
+    };
+
+    rendered.map_err(|e| {
+        // Settings the style or range got from the command line.
+        let failure = match e {
+            Error::InvalidDimensions { .. }
+            | Error::InvalidSettings(_)
+            | Error::InvalidTimeRange { .. } => Failure::Usage,
+            _ => Failure::Render,
+        };
+        (failure, e)
+    })
+}
+
+// The SVG backend has no time range of its own, so the range is cut out of
+// the samples on whole frames.
+fn svg(
+    sample: &MySample,
+    style: &WaveformStyle,
+    range: Option<Range<Duration>>,
+) -> Result<String, Error> {
+    let channels = sample.channels.max(1) as usize;
+    let frames = match range {
+        Some(range) => {
+            if range.end <= range.start {
+                return Err(Error::InvalidTimeRange {
+                    start: range.start,
+                    end: range.end,
+                });
+            }
+            let frame = |time: Duration| {
+                ((time.as_secs_f64() * sample.sample_rate as f64).round() as usize)
+                    .min(sample.frames)
+            };
+            frame(range.start)..frame(range.end)
+        }
+        None => 0..sample.frames,
+    };
+
+    let samples = &sample.samples[frames.start * channels..frames.end * channels];
+    let options = SvgOptions {
+        view_box: true,
+        time_axis: false,
+    };
+    SvgSignal::try_with_style(samples, channels, sample.sample_rate, style, options)
+        .map(SvgSignal::into_string)
+}
+
+fn write_output(output: &Output, bytes: &[u8]) -> io::Result<()> {
+    match output {
+        Output::Stdout => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(bytes)?;
+            stdout.flush()
+        }
+        Output::File(path) => fs::write(path, bytes),
+    }
+}
+
+fn parse_color(value: &str) -> Result<[u8; 4], String> {
+    if value.eq_ignore_ascii_case("transparent") {
+        return Ok([0, 0, 0, 0]);
+    }
+
+    let hex = value.trim_start_matches('#');
+    if !matches!(hex.len(), 6 | 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("expected RRGGBB or RRGGBBAA, got {value}"));
+    }
+
+    let mut color = [0, 0, 0, 255];
+    for (channel, pair) in color.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|e| e.to_string())?;
+        *channel = u8::from_str_radix(pair, 16).map_err(|e| e.to_string())?;
+    }
+
+    Ok(color)
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| format!("not a number: {value}"))?;
+
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
@@ -0,0 +1,116 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+use crate::stream::SharedReader;
+
+// What the container says about the sound, besides the samples themselves.
+// Every field is optional: tags are often missing and some formats rodio can
+// decode are not recognized by the probe.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioMetadata {
+    pub container: Option<String>,
+    pub codec: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+impl AudioMetadata {
+    // Reads the header and tags, then leaves the reader where it started.
+    pub(crate) fn probe<R: Read + Seek + Send + 'static>(reader: &SharedReader<R>) -> Self {
+        let mut metadata = AudioMetadata::default();
+
+        let mut magic = [0; 12];
+        let mut source = reader.clone();
+        if source.read_exact(&mut magic).is_ok() {
+            metadata.container = sniff_container(&magic).map(str::to_string);
+        }
+        if source.seek(SeekFrom::Start(0)).is_err() {
+            return metadata;
+        }
+
+        let stream = MediaSourceStream::new(Box::new(source), Default::default());
+        let probed = symphonia::default::get_probe().format(
+            &Hint::new(),
+            stream,
+            &Default::default(),
+            &Default::default(),
+        );
+
+        if let Ok(mut probed) = probed {
+            // Tags found before the container (ID3v2 on an MP3) come first,
+            // the container's own tags override them.
+            if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+                metadata.read_tags(revision);
+            }
+            if let Some(revision) = probed.format.metadata().current() {
+                metadata.read_tags(revision);
+            }
+
+            if let Some(track) = probed.format.default_track() {
+                metadata.codec = symphonia::default::get_codecs()
+                    .get_codec(track.codec_params.codec)
+                    .map(|codec| codec.short_name.to_string());
+            }
+        }
+
+        let _ = reader.rewind();
+
+        metadata
+    }
+
+    fn read_tags(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            *field = Some(tag.value.to_string());
+        }
+    }
+}
+
+impl<R: Read + Seek + Send> MediaSource for SharedReader<R> {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+pub(crate) fn sniff_container(magic: &[u8]) -> Option<&'static str> {
+    match magic {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', _, ..] => Some("aiff"),
+        [b'f', b'L', b'a', b'C', ..] => Some("flac"),
+        [b'O', b'g', b'g', b'S', ..] => Some("ogg"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("mp4"),
+        [0x1a, 0x45, 0xdf, 0xa3, ..] => Some("mkv"),
+        [b'c', b'a', b'f', b'f', ..] => Some("caf"),
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        [0xff, second, ..] if second & 0xf6 == 0xf0 => Some("aac"),
+        [0xff, second, ..] if second & 0xe0 == 0xe0 => Some("mp3"),
+        _ => None,
+    }
+}
+
+// Exact length of `frames` frames, without going through floating point.
+pub(crate) fn frames_to_duration(frames: usize, sample_rate: u32) -> Duration {
+    if sample_rate == 0 {
+        return Duration::ZERO;
+    }
+    let rate = sample_rate as u64;
+    let frames = frames as u64;
+    let nanos = (frames % rate) as u128 * 1_000_000_000 / rate as u128;
+
+    Duration::from_secs(frames / rate) + Duration::from_nanos(nanos as u64)
+}
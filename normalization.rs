@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+
+use crate::core::audio_process::find_highest_sample;
+use crate::core::MySample;
+use crate::sample::PcmSample;
+
+// Sample rate assumed for loudness measurement when a raw slice comes without one.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+const BLOCK_STEPS: usize = 4;
+const BLOCK_STEP_SECONDS: f64 = 0.1;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_DB: f64 = -10.0;
+
+// How sample amplitudes are scaled to the height of the image. Every variant
+// ends up as a gain; a gain that would be infinite (silence) falls back to 1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Normalization {
+    // The loudest absolute sample touches the edge of its lane.
+    #[default]
+    Peak,
+    // The RMS level is drawn at `target` times the half height of the lane.
+    Rms {
+        target: f32,
+    },
+    // Gated K-weighted loudness (ITU-R BS.1770 style) is brought to `target_lufs`.
+    Loudness {
+        target_lufs: f32,
+    },
+    // A sample at `reference_db` dBFS touches the edge, whatever the content.
+    Fixed {
+        reference_db: f32,
+    },
+    // Explicit linear gain, as returned by `Normalization::shared`.
+    Gain(f32),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LevelStats {
+    pub peak: f32,
+    sum_squares: f64,
+    sample_count: usize,
+    block_powers: Vec<f64>,
+}
+
+impl LevelStats {
+    pub(crate) fn from_peak(peak: f32) -> Self {
+        LevelStats {
+            peak,
+            ..LevelStats::default()
+        }
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.sample_count == 0 {
+            return 0.0;
+        }
+
+        (self.sum_squares / self.sample_count as f64).sqrt() as f32
+    }
+
+    pub fn loudness_lufs(&self) -> Option<f64> {
+        let above_absolute: Vec<f64> = self
+            .block_powers
+            .iter()
+            .copied()
+            .filter(|&p| lufs(p) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if above_absolute.is_empty() {
+            return None;
+        }
+
+        let relative_gate = lufs(mean(&above_absolute)) + RELATIVE_GATE_DB;
+        let gated: Vec<f64> = above_absolute
+            .into_iter()
+            .filter(|&p| lufs(p) > relative_gate)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+
+        Some(lufs(mean(&gated)))
+    }
+
+    pub fn merge(&mut self, other: &LevelStats) {
+        self.peak = self.peak.max(other.peak);
+        self.sum_squares += other.sum_squares;
+        self.sample_count += other.sample_count;
+        self.block_powers.extend_from_slice(&other.block_powers);
+    }
+}
+
+impl Normalization {
+    // Only gathers what this normalization needs: loudness blocks are costly.
+    pub fn measure<T: PcmSample>(
+        &self,
+        sound: &[T],
+        channels: usize,
+        sample_rate: u32,
+    ) -> LevelStats {
+        if let Normalization::Peak = self {
+            return LevelStats {
+                peak: find_highest_sample(sound),
+                ..LevelStats::default()
+            };
+        }
+
+        let mut meter = LevelMeter::new(*self, channels, sample_rate);
+        for frame in sound.chunks_exact(channels.max(1)) {
+            meter.push_frame(frame.iter().map(|s| s.to_f32()));
+        }
+
+        meter.finish()
+    }
+
+    pub fn gain_for(&self, stats: &LevelStats) -> f32 {
+        let gain = match *self {
+            Normalization::Peak => 1.0 / stats.peak,
+            Normalization::Rms { target } => target / stats.rms(),
+            Normalization::Loudness { target_lufs } => match stats.loudness_lufs() {
+                Some(measured) => 10f64.powf((target_lufs as f64 - measured) / 20.0) as f32,
+                None => 1.0,
+            },
+            Normalization::Fixed { reference_db } => 10f32.powf(-reference_db / 20.0),
+            Normalization::Gain(gain) => gain,
+        };
+
+        if gain.is_finite() && gain > 0.0 {
+            gain
+        } else {
+            1.0
+        }
+    }
+
+    pub fn gain<T: PcmSample>(&self, sound: &[T], channels: usize, sample_rate: u32) -> f32 {
+        self.gain_for(&self.measure(sound, channels, sample_rate))
+    }
+
+    // One scale for several files, so that their images can be compared.
+    // Each item is (interleaved sound, channels, sample rate).
+    pub fn shared<'a, T: PcmSample + 'a>(
+        &self,
+        sounds: impl IntoIterator<Item = (&'a [T], usize, u32)>,
+    ) -> Normalization {
+        let mut stats = LevelStats::default();
+        for (sound, channels, sample_rate) in sounds {
+            stats.merge(&self.measure(sound, channels, sample_rate));
+        }
+
+        Normalization::Gain(self.gain_for(&stats))
+    }
+
+    pub fn shared_for_samples<'a>(
+        &self,
+        samples: impl IntoIterator<Item = &'a MySample>,
+    ) -> Normalization {
+        self.shared(samples.into_iter().map(|sample| {
+            (
+                sample.samples.as_slice(),
+                sample.channels as usize,
+                sample.sample_rate,
+            )
+        }))
+    }
+}
+
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Measures a sound frame by frame, for callers that never hold it whole.
+// Gives the same `LevelStats` as `Normalization::measure`.
+pub struct LevelMeter {
+    normalization: Normalization,
+    stats: LevelStats,
+    loudness: Option<LoudnessMeter>,
+}
+
+impl LevelMeter {
+    pub fn new(normalization: Normalization, channels: usize, sample_rate: u32) -> Self {
+        let loudness = match normalization {
+            Normalization::Loudness { .. } => Some(LoudnessMeter::new(channels, sample_rate)),
+            _ => None,
+        };
+
+        LevelMeter {
+            normalization,
+            stats: LevelStats::default(),
+            loudness,
+        }
+    }
+
+    pub fn push_frame(&mut self, frame: impl IntoIterator<Item = f32>) {
+        match self.normalization {
+            Normalization::Peak => {
+                for s in frame {
+                    self.stats.peak = self.stats.peak.max(s.abs());
+                }
+            }
+            Normalization::Rms { .. } => {
+                for s in frame {
+                    self.stats.sum_squares += (s as f64) * (s as f64);
+                    self.stats.sample_count += 1;
+                }
+            }
+            Normalization::Loudness { .. } => {
+                if let Some(loudness) = self.loudness.as_mut() {
+                    loudness.push_frame(frame);
+                }
+            }
+            Normalization::Fixed { .. } | Normalization::Gain(_) => {}
+        }
+    }
+
+    pub fn finish(mut self) -> LevelStats {
+        if let Some(loudness) = self.loudness {
+            self.stats.block_powers = loudness.block_powers();
+        }
+
+        self.stats
+    }
+}
+
+// Mean square of the K-weighted signal over 400 ms blocks overlapping by 75 %,
+// summed over channels.
+struct LoudnessMeter {
+    filters: Vec<KWeighting>,
+    step_frames: usize,
+    step_energies: Vec<f64>,
+    energy: f64,
+    total_energy: f64,
+    frames: usize,
+}
+
+impl LoudnessMeter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        LoudnessMeter {
+            filters: vec![KWeighting::new(sample_rate); channels.max(1)],
+            step_frames: ((sample_rate as f64 * BLOCK_STEP_SECONDS).round() as usize).max(1),
+            step_energies: vec![],
+            energy: 0.0,
+            total_energy: 0.0,
+            frames: 0,
+        }
+    }
+
+    fn push_frame(&mut self, frame: impl IntoIterator<Item = f32>) {
+        for (filter, s) in self.filters.iter_mut().zip(frame) {
+            let y = filter.process(s as f64);
+            self.energy += y * y;
+        }
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(self.step_frames) {
+            self.step_energies.push(self.energy);
+            self.total_energy += self.energy;
+            self.energy = 0.0;
+        }
+    }
+
+    fn block_powers(self) -> Vec<f64> {
+        if self.frames == 0 {
+            return vec![];
+        }
+        if self.step_energies.len() < BLOCK_STEPS {
+            return vec![(self.total_energy + self.energy) / self.frames as f64];
+        }
+
+        self.step_energies
+            .windows(BLOCK_STEPS)
+            .map(|w| w.iter().sum::<f64>() / (BLOCK_STEPS * self.step_frames) as f64)
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+
+        y
+    }
+}
+
+// Pre-filter (high shelf) followed by the RLB high-pass, with coefficients
+// derived for any sample rate.
+#[derive(Clone, Copy, Debug)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate.max(1) as f64;
+
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (std::f64::consts::PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (std::f64::consts::PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        KWeighting { shelf, high_pass }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(x))
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use ab_glyph::PxScale;
+use imageproc::drawing::draw_text_mut;
+use imageproc::image::Rgba;
+use serde::{Deserialize, Serialize};
+
+use crate::annotate::font;
+use crate::core::audio_process::{blend_over, fill_span};
+use crate::core::ViewSignal;
+use crate::layout::TimeLayout;
+
+const LABEL_FONT_SIZE: f32 = 12.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub time: Duration,
+    pub label: Option<String>,
+    pub color: [u8; 3],
+    pub opacity: f32,
+}
+
+// A [start, end) span of time, shaded at `opacity` with solid edges.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub start: Duration,
+    pub end: Duration,
+    pub label: Option<String>,
+    pub color: [u8; 3],
+    pub opacity: f32,
+}
+
+impl ViewSignal {
+    // Regions first so that markers stay visible inside them.
+    pub fn draw_overlays(&mut self, layout: &TimeLayout, regions: &[Region], markers: &[Marker]) {
+        for region in regions {
+            self.draw_region(layout, region);
+        }
+        for marker in markers {
+            self.draw_marker(layout, marker);
+        }
+    }
+
+    pub fn draw_region(&mut self, layout: &TimeLayout, region: &Region) {
+        let image = self.image_mut();
+        let (width, height) = image.dimensions();
+        let fill = overlay_color(region.color, region.opacity);
+        let edge = overlay_color(region.color, 1.0);
+
+        let left = layout.x_at(region.start);
+        let right = layout.x_at(region.end);
+        if right <= 0.0 || left >= width as f64 || right <= left {
+            return;
+        }
+
+        let first = left.max(0.0).round() as u32;
+        let last = (right.min(width as f64).round() as u32).max(first + 1);
+        for x in first..last.min(width) {
+            for y in 0..height {
+                blend_over(image.get_pixel_mut(x, y), fill, 1.0);
+            }
+        }
+        for x in [left, right - 1.0] {
+            fill_span(image, x.round() as i64, 0.0, height as f32, |_| edge, false);
+        }
+
+        if let Some(label) = &region.label {
+            self.draw_label(label, first as i32 + 2, region.color);
+        }
+    }
+
+    pub fn draw_marker(&mut self, layout: &TimeLayout, marker: &Marker) {
+        let image = self.image_mut();
+        let (width, height) = image.dimensions();
+        let x = layout.x_at(marker.time).round();
+        if x < 0.0 || x >= width as f64 {
+            return;
+        }
+
+        let color = overlay_color(marker.color, marker.opacity);
+        fill_span(image, x as i64, 0.0, height as f32, |_| color, false);
+
+        if let Some(label) = &marker.label {
+            self.draw_label(label, x as i32 + 2, marker.color);
+        }
+    }
+
+    fn draw_label(&mut self, label: &str, x: i32, color: [u8; 3]) {
+        draw_text_mut(
+            self.image_mut(),
+            overlay_color(color, 1.0),
+            x,
+            1,
+            PxScale::from(LABEL_FONT_SIZE),
+            &font(),
+            label,
+        );
+    }
+}
+
+fn overlay_color(color: [u8; 3], opacity: f32) -> Rgba<u8> {
+    let [r, g, b] = color;
+    let alpha = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    Rgba([r, g, b, alpha])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::style::WaveformStyle;
+
+    #[test]
+    fn regions_shade_between_solid_edges_under_markers() {
+        let style = WaveformStyle {
+            size: [10, 4],
+            background_color: [255; 4],
+            ..Default::default()
+        };
+        let mut view = ViewSignal::try_with_style(&[0.0f32; 80], 1, 8, &style).unwrap();
+        let layout = TimeLayout::from_pixels_per_second(1.0, 8);
+        let region = Region {
+            start: Duration::from_secs(2),
+            end: Duration::from_secs(6),
+            label: None,
+            color: [255, 0, 0],
+            opacity: 0.5,
+        };
+        let marker = Marker {
+            time: Duration::from_secs(4),
+            label: None,
+            color: [0, 0, 255],
+            opacity: 1.0,
+        };
+
+        view.draw_overlays(&layout, &[region], &[marker]);
+
+        let pixel = |x: usize, y: usize| -> [u8; 4] {
+            let offset = (y * 10 + x) * 4;
+            view.as_bytes()[offset..offset + 4].try_into().unwrap()
+        };
+        // Rows clear of the silent wave.
+        for y in [0, 3] {
+            assert_eq!(pixel(1, y), [255; 4]);
+            assert_eq!(pixel(2, y), [255, 0, 0, 255]);
+            let [r, g, b, a] = pixel(3, y);
+            assert!(r == 255 && g == b && (100..156).contains(&g) && a == 255);
+            assert_eq!(pixel(4, y), [0, 0, 255, 255]);
+            assert_eq!(pixel(5, y), [255, 0, 0, 255]);
+            assert_eq!(pixel(6, y), [255; 4]);
+        }
+    }
+}
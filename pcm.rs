@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::ViewSignal;
+use crate::error::Error;
+use crate::metadata::frames_to_duration;
+use crate::sample::{PackedI24, PcmSample};
+use crate::stream::WaveformStream;
+use crate::style::WaveformStyle;
+
+// Frames decoded at a time when rendering. Only this many are ever held as
+// f32, whatever the size of the buffer.
+const DECODE_FRAMES: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PcmFormat {
+    // Unsigned, centered on 128.
+    U8,
+    I16,
+    // Three bytes per sample, no padding.
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl PcmFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            PcmFormat::U8 => 1,
+            PcmFormat::I16 => 2,
+            PcmFormat::I24 => 3,
+            PcmFormat::I32 | PcmFormat::F32 => 4,
+            PcmFormat::F64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PcmLayout {
+    // One sample of each channel per frame, frame after frame.
+    #[default]
+    Interleaved,
+    // Every sample of the first channel, then every sample of the next one.
+    Planar,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PcmDescriptor {
+    pub format: PcmFormat,
+    pub endianness: Endianness,
+    pub channels: usize,
+    pub layout: PcmLayout,
+    pub sample_rate: u32,
+}
+
+impl PcmDescriptor {
+    pub fn frame_size(&self) -> usize {
+        self.format.bytes_per_sample() * self.channels
+    }
+}
+
+// Raw PCM bytes read in place through their descriptor.
+#[derive(Clone, Copy, Debug)]
+pub struct PcmSource<'a> {
+    bytes: &'a [u8],
+    descriptor: PcmDescriptor,
+}
+
+impl<'a> PcmSource<'a> {
+    // `bytes` must hold whole frames; with a planar layout it is split in
+    // `channels` planes of the same length.
+    pub fn new(bytes: &'a [u8], descriptor: PcmDescriptor) -> Result<Self, Error> {
+        if descriptor.channels == 0 {
+            return Err(Error::InvalidSettings(
+                "pcm needs at least one channel".into(),
+            ));
+        }
+        // The most a decoded `MySample` can carry; it also keeps the frame
+        // size and the decode block from overflowing.
+        if descriptor.channels > u16::MAX as usize {
+            return Err(Error::InvalidSettings(format!(
+                "{} pcm channels, at most {} are supported",
+                descriptor.channels,
+                u16::MAX
+            )));
+        }
+        if descriptor.sample_rate == 0 {
+            return Err(Error::InvalidSettings("pcm sample rate is zero".into()));
+        }
+        if !bytes.len().is_multiple_of(descriptor.frame_size()) {
+            return Err(Error::InvalidSettings(format!(
+                "{} pcm bytes are not a whole number of {}-byte frames",
+                bytes.len(),
+                descriptor.frame_size()
+            )));
+        }
+
+        Ok(PcmSource { bytes, descriptor })
+    }
+
+    pub fn descriptor(&self) -> &PcmDescriptor {
+        &self.descriptor
+    }
+
+    pub fn frames(&self) -> usize {
+        self.bytes.len() / self.descriptor.frame_size()
+    }
+
+    pub fn duration(&self) -> Duration {
+        frames_to_duration(self.frames(), self.descriptor.sample_rate)
+    }
+
+    // Sample of `channel` in frame `frame`, in [-1, 1].
+    pub fn sample(&self, frame: usize, channel: usize) -> f32 {
+        let size = self.descriptor.format.bytes_per_sample();
+        let offset = self.sample_offset(frame, channel);
+        let mut value = [0.0];
+        decode(
+            self.descriptor.format,
+            self.descriptor.endianness,
+            &self.bytes[offset..offset + size],
+            &mut value,
+        );
+
+        value[0]
+    }
+
+    // Calls `f` with interleaved f32 frames, at most `DECODE_FRAMES` at a
+    // time, in order.
+    pub fn for_each_block(&self, mut f: impl FnMut(&[f32])) {
+        let PcmDescriptor {
+            format,
+            endianness,
+            channels,
+            layout,
+            ..
+        } = self.descriptor;
+        let size = format.bytes_per_sample();
+        // No bigger than the buffer, which matters with many channels.
+        let mut block = vec![0.0f32; DECODE_FRAMES.min(self.frames()) * channels];
+
+        match layout {
+            PcmLayout::Interleaved => {
+                for chunk in self
+                    .bytes
+                    .chunks(DECODE_FRAMES * self.descriptor.frame_size())
+                {
+                    let samples = chunk.len() / size;
+                    decode(format, endianness, chunk, &mut block[..samples]);
+                    f(&block[..samples]);
+                }
+            }
+            PcmLayout::Planar => {
+                let mut plane = vec![0.0f32; DECODE_FRAMES];
+                let frames = self.frames();
+                for first in (0..frames).step_by(DECODE_FRAMES) {
+                    let count = DECODE_FRAMES.min(frames - first);
+                    for channel in 0..channels {
+                        let offset = self.sample_offset(first, channel);
+                        let bytes = &self.bytes[offset..offset + count * size];
+                        decode(format, endianness, bytes, &mut plane[..count]);
+                        for (i, &s) in plane[..count].iter().enumerate() {
+                            block[i * channels + channel] = s;
+                        }
+                    }
+                    f(&block[..count * channels]);
+                }
+            }
+        }
+    }
+
+    fn sample_offset(&self, frame: usize, channel: usize) -> usize {
+        let size = self.descriptor.format.bytes_per_sample();
+        match self.descriptor.layout {
+            PcmLayout::Interleaved => (frame * self.descriptor.channels + channel) * size,
+            PcmLayout::Planar => (channel * self.frames() + frame) * size,
+        }
+    }
+}
+
+impl ViewSignal {
+    // Decodes the bytes block by block into a `WaveformStream`, so nothing
+    // the size of the sound is allocated besides the image. The result is
+    // the one `try_with_style` gives for the same samples as f32.
+    pub fn try_from_pcm(source: &PcmSource, style: &WaveformStyle) -> Result<Self, Error> {
+        let descriptor = source.descriptor();
+        let mut stream = WaveformStream::try_new(
+            style,
+            descriptor.channels,
+            descriptor.sample_rate,
+            source.frames(),
+        )?;
+        source.for_each_block(|block| stream.push_interleaved(block));
+
+        stream.finish()
+    }
+}
+
+// Decodes as many samples as `out` holds from the front of `bytes`.
+fn decode(format: PcmFormat, endianness: Endianness, bytes: &[u8], out: &mut [f32]) {
+    macro_rules! decode_as {
+        ($sample:ty, $size:expr) => {{
+            let samples = bytes
+                .chunks_exact($size)
+                .map(|b| <[u8; $size]>::try_from(b).expect("chunk of the sample size"));
+            match endianness {
+                Endianness::Little => {
+                    for (converted, b) in out.iter_mut().zip(samples) {
+                        *converted = <$sample>::from_le_bytes(b).to_f32();
+                    }
+                }
+                Endianness::Big => {
+                    for (converted, b) in out.iter_mut().zip(samples) {
+                        *converted = <$sample>::from_be_bytes(b).to_f32();
+                    }
+                }
+            }
+        }};
+    }
+
+    match format {
+        PcmFormat::U8 => u8::convert(bytes, out),
+        PcmFormat::I16 => decode_as!(i16, 2),
+        PcmFormat::I24 => match endianness {
+            Endianness::Little => PackedI24::convert(PackedI24::from_bytes(bytes), out),
+            Endianness::Big => {
+                for (converted, b) in out.iter_mut().zip(bytes.chunks_exact(3)) {
+                    *converted = PackedI24([b[2], b[1], b[0]]).to_f32();
+                }
+            }
+        },
+        PcmFormat::I32 => decode_as!(i32, 4),
+        PcmFormat::F32 => decode_as!(f32, 4),
+        PcmFormat::F64 => decode_as!(f64, 8),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn raw_pcm_bytes_render_like_the_samples() {
+        let style = WaveformStyle {
+            size: [50, 40],
+            ..Default::default()
+        };
+        let pcm: Vec<i16> = (0..2 * 3000)
+            .map(|i| ((i as f32 * 0.05).sin() * (i % 11) as f32 * 2000.0) as i16)
+            .collect();
+        let expected = ViewSignal::try_with_style(&pcm, 2, 8000, &style).unwrap();
+
+        let interleaved: Vec<u8> = pcm.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let planar: Vec<u8> = (0..2)
+            .flat_map(|c| pcm.iter().skip(c).step_by(2).flat_map(|s| s.to_be_bytes()))
+            .collect();
+
+        for (bytes, endianness, layout) in [
+            (&interleaved, Endianness::Little, PcmLayout::Interleaved),
+            (&planar, Endianness::Big, PcmLayout::Planar),
+        ] {
+            let descriptor = PcmDescriptor {
+                format: PcmFormat::I16,
+                endianness,
+                channels: 2,
+                layout,
+                sample_rate: 8000,
+            };
+            let source = PcmSource::new(bytes, descriptor).unwrap();
+            assert_eq!(source.frames(), 3000);
+            assert_eq!(source.sample(10, 1), pcm[21] as f32 / 32768.0);
+
+            let view = ViewSignal::try_from_pcm(&source, &style).unwrap();
+            assert!(view.as_bytes() == expected.as_bytes());
+        }
+
+        let descriptor = PcmDescriptor {
+            format: PcmFormat::I24,
+            endianness: Endianness::Little,
+            channels: 2,
+            layout: PcmLayout::Interleaved,
+            sample_rate: 8000,
+        };
+        assert!(matches!(
+            PcmSource::new(&[0; 7], descriptor),
+            Err(Error::InvalidSettings(_))
+        ));
+
+        let wide = PcmDescriptor {
+            channels: u16::MAX as usize,
+            ..descriptor
+        };
+        let mut blocks = 0;
+        PcmSource::new(&[], wide)
+            .unwrap()
+            .for_each_block(|_| blocks += 1);
+        assert_eq!(blocks, 0);
+
+        for channels in [usize::MAX / 2 + 1, u16::MAX as usize + 1] {
+            let descriptor = PcmDescriptor {
+                channels,
+                ..descriptor
+            };
+            assert!(matches!(
+                PcmSource::new(&[], descriptor),
+                Err(Error::InvalidSettings(_))
+            ));
+        }
+    }
+}
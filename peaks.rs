@@ -0,0 +1,454 @@
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::audio_process::{lane_area, ColumnPeak, Lane};
+use crate::core::{image_len, ChannelMode, ViewSignal};
+use crate::error::Error;
+use crate::normalization::LevelStats;
+use crate::sample::PcmSample;
+use crate::style::WaveformStyle;
+
+const DAT_FLAG_8_BIT: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeakLevel {
+    pub samples_per_pixel: usize,
+    // [min, max] per pixel, channels interleaved inside each pixel.
+    pub data: Vec<[f32; 2]>,
+}
+
+// Min/max envelope of a sound at several resolutions, enough to draw it at
+// any zoom without the audio. Compatible with BBC audiowaveform .dat and
+// .json files, which hold a single level each.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveformPeaks {
+    pub sample_rate: u32,
+    pub channels: usize,
+    // Finest level first; every level covers the whole sound.
+    pub levels: Vec<PeakLevel>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AudiowaveformJson {
+    version: u32,
+    #[serde(default = "one_channel")]
+    channels: usize,
+    sample_rate: u32,
+    samples_per_pixel: usize,
+    bits: u8,
+    length: usize,
+    data: Vec<i32>,
+}
+
+fn one_channel() -> usize {
+    1
+}
+
+impl WaveformPeaks {
+    // `levels` levels, the first one at `samples_per_pixel` and each next one
+    // twice as coarse.
+    pub fn from_interleaved<T: PcmSample>(
+        sound: &[T],
+        channels: usize,
+        sample_rate: u32,
+        samples_per_pixel: usize,
+        levels: usize,
+    ) -> Self {
+        let channels = channels.max(1);
+        let samples_per_pixel = samples_per_pixel.max(1);
+
+        let mut data = vec![];
+        for pixel in sound.chunks(samples_per_pixel * channels) {
+            for c in 0..channels {
+                let min_max = pixel
+                    .iter()
+                    .skip(c)
+                    .step_by(channels)
+                    .map(|s| s.to_f32())
+                    .fold([f32::MAX, f32::MIN], |[min, max], s| {
+                        [min.min(s), max.max(s)]
+                    });
+                data.push(if min_max[0] > min_max[1] {
+                    [0.0, 0.0]
+                } else {
+                    min_max
+                });
+            }
+        }
+
+        WaveformPeaks {
+            sample_rate,
+            channels,
+            levels: vec![PeakLevel {
+                samples_per_pixel,
+                data,
+            }],
+        }
+        .with_levels(levels)
+    }
+
+    // Adds coarser levels, each merging pairs of pixels of the previous one,
+    // until there are `levels` of them.
+    pub fn with_levels(mut self, levels: usize) -> Self {
+        while self.levels.len() < levels {
+            let Some(previous) = self.levels.last() else {
+                break;
+            };
+
+            let pixels: Vec<&[[f32; 2]]> = previous.data.chunks(self.channels).collect();
+            let mut data = Vec::with_capacity(previous.data.len() / 2 + self.channels);
+            for pair in pixels.chunks(2) {
+                for c in 0..self.channels {
+                    data.push(
+                        pair.iter()
+                            .map(|pixel| pixel[c])
+                            .reduce(merge)
+                            .unwrap_or_default(),
+                    );
+                }
+            }
+
+            let level = PeakLevel {
+                samples_per_pixel: previous.samples_per_pixel * 2,
+                data,
+            };
+            self.levels.push(level);
+        }
+
+        self
+    }
+
+    pub fn pixels(&self, level: usize) -> usize {
+        self.levels
+            .get(level)
+            .map_or(0, |l| l.data.len() / self.channels.max(1))
+    }
+
+    pub fn read_dat(mut reader: impl Read) -> Result<Self, Error> {
+        let version = read_u32(&mut reader)?;
+        if version != 1 && version != 2 {
+            return Err(Error::PeakFormat(format!("unsupported version {version}")));
+        }
+        let flags = read_u32(&mut reader)?;
+        let sample_rate = read_u32(&mut reader)?;
+        let samples_per_pixel = read_u32(&mut reader)? as usize;
+        let length = read_u32(&mut reader)? as usize;
+        let channels = if version == 2 {
+            read_u32(&mut reader)? as usize
+        } else {
+            1
+        };
+        if channels == 0 || samples_per_pixel == 0 {
+            return Err(Error::PeakFormat(
+                "zero channels or samples per pixel".into(),
+            ));
+        }
+
+        let bits = if flags & DAT_FLAG_8_BIT != 0 { 8 } else { 16 };
+        // The header is untrusted: the payload is read as it comes rather
+        // than allocated up front from `length`.
+        let size = length
+            .checked_mul(channels)
+            .and_then(|n| n.checked_mul(2 * (bits / 8)))
+            .ok_or_else(|| {
+                Error::PeakFormat(format!(
+                    "length {length} of {channels} channels is too large"
+                ))
+            })?;
+        let mut raw = Vec::new();
+        reader.take(size as u64).read_to_end(&mut raw)?;
+        if raw.len() != size {
+            return Err(Error::PeakFormat(format!(
+                "{} bytes of peaks instead of {size}",
+                raw.len()
+            )));
+        }
+
+        let values: Vec<i32> = if bits == 8 {
+            raw.iter().map(|&b| b as i8 as i32).collect()
+        } else {
+            raw.chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as i32)
+                .collect()
+        };
+
+        Ok(WaveformPeaks {
+            sample_rate,
+            channels,
+            levels: vec![PeakLevel {
+                samples_per_pixel,
+                data: dequantize(&values, bits as u8),
+            }],
+        })
+    }
+
+    pub fn write_dat(&self, level: usize, bits: u8, mut writer: impl Write) -> Result<(), Error> {
+        let peak_level = self.level(level)?;
+        let values = quantize(&peak_level.data, bits)?;
+
+        let flags = if bits == 8 { DAT_FLAG_8_BIT } else { 0 };
+        for field in [
+            2,
+            flags,
+            self.sample_rate,
+            peak_level.samples_per_pixel as u32,
+            self.pixels(level) as u32,
+            self.channels as u32,
+        ] {
+            writer.write_all(&field.to_le_bytes())?;
+        }
+
+        let mut raw = Vec::with_capacity(values.len() * 2);
+        for v in values {
+            if bits == 8 {
+                raw.push(v as i8 as u8);
+            } else {
+                raw.extend_from_slice(&(v as i16).to_le_bytes());
+            }
+        }
+        writer.write_all(&raw)?;
+
+        Ok(())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let file: AudiowaveformJson = serde_json::from_str(json)?;
+        if file.version != 1 && file.version != 2 {
+            return Err(Error::PeakFormat(format!(
+                "unsupported version {}",
+                file.version
+            )));
+        }
+        if file.bits != 8 && file.bits != 16 {
+            return Err(Error::PeakFormat(format!("unsupported bits {}", file.bits)));
+        }
+        if file.channels == 0 || file.samples_per_pixel == 0 {
+            return Err(Error::PeakFormat(
+                "zero channels or samples per pixel".into(),
+            ));
+        }
+        let expected = file
+            .length
+            .checked_mul(file.channels)
+            .and_then(|n| n.checked_mul(2));
+        if expected != Some(file.data.len()) {
+            return Err(Error::PeakFormat(format!(
+                "{} values for a length of {}",
+                file.data.len(),
+                file.length
+            )));
+        }
+
+        Ok(WaveformPeaks {
+            sample_rate: file.sample_rate,
+            channels: file.channels,
+            levels: vec![PeakLevel {
+                samples_per_pixel: file.samples_per_pixel,
+                data: dequantize(&file.data, file.bits),
+            }],
+        })
+    }
+
+    pub fn to_json(&self, level: usize, bits: u8) -> Result<String, Error> {
+        let peak_level = self.level(level)?;
+
+        let file = AudiowaveformJson {
+            version: 2,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            samples_per_pixel: peak_level.samples_per_pixel,
+            bits,
+            length: self.pixels(level),
+            data: quantize(&peak_level.data, bits)?,
+        };
+
+        Ok(serde_json::to_string(&file)?)
+    }
+
+    fn level(&self, level: usize) -> Result<&PeakLevel, Error> {
+        self.levels
+            .get(level)
+            .ok_or_else(|| Error::PeakFormat(format!("no level {level}")))
+    }
+
+    // Coarsest level that still has at least one pixel per image column.
+    fn level_for(&self, samples_per_column: f64) -> Option<&PeakLevel> {
+        self.levels
+            .iter()
+            .filter(|l| l.samples_per_pixel as f64 <= samples_per_column)
+            .max_by_key(|l| l.samples_per_pixel)
+            .or_else(|| self.levels.iter().min_by_key(|l| l.samples_per_pixel))
+    }
+}
+
+impl ViewSignal {
+    // Draws `range` of the sound from its peaks only. Peak files carry no RMS,
+    // so `RenderMode::Rms` and RMS or loudness normalization are not
+    // available here; `Normalization::Peak` uses the peak of the whole sound
+    // so that every zoom level shares one scale. `ChannelMode::MixDown` draws
+    // the envelope around all channels.
+    pub fn try_from_peaks(
+        peaks: &WaveformPeaks,
+        range: Range<Duration>,
+        style: &WaveformStyle,
+    ) -> Result<Self, Error> {
+        image_len(style.size)?;
+        if range.end <= range.start {
+            return Err(Error::InvalidTimeRange {
+                start: range.start,
+                end: range.end,
+            });
+        }
+
+        let width = style.size[0];
+        let channels = peaks.channels.max(1);
+        let sample_rate = peaks.sample_rate as f64;
+        let start = range.start.as_secs_f64() * sample_rate;
+        let end = range.end.as_secs_f64() * sample_rate;
+        let samples_per_column = (end - start).max(0.0) / width.max(1) as f64;
+
+        let Some(level) = peaks.level_for(samples_per_column) else {
+            return ViewSignal::render_lanes(Vec::new(), 1.0, style);
+        };
+        let pixels = level.data.len() / channels;
+        let spp = level.samples_per_pixel as f64;
+
+        let column = |x: usize, c: usize| -> Option<[f32; 2]> {
+            let from = start + x as f64 * samples_per_column;
+            let first = (from / spp).floor() as usize;
+            let last = (((from + samples_per_column) / spp).ceil() as usize)
+                .max(first.saturating_add(1))
+                .min(pixels);
+
+            (first..last)
+                .map(|p| level.data[p * channels + c])
+                .reduce(merge)
+        };
+        let to_peak = |min_max: Option<[f32; 2]>| {
+            min_max.map_or(ColumnPeak::default(), |[min, max]| ColumnPeak {
+                min,
+                max,
+                rms: 0.0,
+            })
+        };
+
+        let envelopes: Vec<Vec<ColumnPeak>> = match style.channel_mode {
+            ChannelMode::Lanes | ChannelMode::Overlay => (0..channels)
+                .map(|c| (0..width).map(|x| to_peak(column(x, c))).collect())
+                .collect(),
+            ChannelMode::MixDown => vec![(0..width)
+                .map(|x| to_peak((0..channels).filter_map(|c| column(x, c)).reduce(merge)))
+                .collect()],
+        };
+
+        let lanes: Vec<Lane> = envelopes
+            .into_iter()
+            .enumerate()
+            .map(|(c, envelope)| Lane {
+                area: lane_area(style, channels, c),
+                envelope,
+                centroids: Vec::new(),
+            })
+            .collect();
+
+        let peak = peaks.levels[0]
+            .data
+            .iter()
+            .fold(0.0f32, |peak, [min, max]| {
+                peak.max(min.abs()).max(max.abs())
+            });
+        let wave_ratio = style.normalization.gain_for(&LevelStats::from_peak(peak));
+
+        ViewSignal::render_lanes(lanes, wave_ratio, style)
+    }
+}
+
+fn merge(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0].min(b[0]), a[1].max(b[1])]
+}
+
+fn quantize(data: &[[f32; 2]], bits: u8) -> Result<Vec<i32>, Error> {
+    let (scale, lowest, highest) = match bits {
+        8 => (128.0, i8::MIN as f32, i8::MAX as f32),
+        16 => (32768.0, i16::MIN as f32, i16::MAX as f32),
+        _ => return Err(Error::PeakFormat(format!("unsupported bits {bits}"))),
+    };
+
+    Ok(data
+        .iter()
+        .flatten()
+        .map(|v| (v * scale).round().clamp(lowest, highest) as i32)
+        .collect())
+}
+
+fn dequantize(values: &[i32], bits: u8) -> Vec<[f32; 2]> {
+    let scale = if bits == 8 { 128.0 } else { 32768.0 };
+
+    values
+        .chunks_exact(2)
+        .map(|pair| [pair[0] as f32 / scale, pair[1] as f32 / scale])
+        .collect()
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, Error> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn peaks_survive_a_dat_round_trip() {
+        let sound: Vec<f32> = vec![0.5, -0.25, 0.875, -1.0, 0.0, 0.25, -0.5, 0.75];
+        let peaks = WaveformPeaks::from_interleaved(&sound, 2, 8000, 2, 2);
+
+        assert_eq!(
+            peaks.levels[0].data,
+            vec![[0.5, 0.875], [-1.0, -0.25], [-0.5, 0.0], [0.25, 0.75]]
+        );
+        assert_eq!(peaks.levels[1].data, vec![[-0.5, 0.875], [-1.0, 0.75]]);
+
+        let mut dat = vec![];
+        peaks.write_dat(1, 16, &mut dat).unwrap();
+        let loaded = WaveformPeaks::read_dat(dat.as_slice()).unwrap();
+
+        assert_eq!(loaded.channels, 2);
+        assert_eq!(loaded.levels[0].samples_per_pixel, 4);
+        assert_eq!(loaded.levels[0].data, peaks.levels[1].data);
+    }
+
+    #[test]
+    fn hostile_peak_headers_are_format_errors() {
+        let header = |length: u32, channels: u32| -> Vec<u8> {
+            [2, 0, 8000, 256, length, channels]
+                .iter()
+                .flat_map(|field: &u32| field.to_le_bytes())
+                .collect()
+        };
+
+        for dat in [header(u32::MAX, u32::MAX), header(1 << 20, 2)] {
+            assert!(matches!(
+                WaveformPeaks::read_dat(dat.as_slice()),
+                Err(Error::PeakFormat(_))
+            ));
+        }
+
+        let peaks = WaveformPeaks::from_interleaved(&[0.5f32, -0.5], 1, 8000, 1, 1);
+        let reversed = Duration::from_millis(800)..Duration::from_millis(100);
+        assert!(matches!(
+            ViewSignal::try_from_peaks(&peaks, reversed, &WaveformStyle::default()),
+            Err(Error::InvalidTimeRange { .. })
+        ));
+
+        // Past the last peak every column is empty, even at the end of time.
+        let late = Duration::MAX - Duration::from_secs(1)..Duration::MAX;
+        assert!(ViewSignal::try_from_peaks(&peaks, late, &WaveformStyle::default()).is_ok());
+    }
+}
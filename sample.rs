@@ -0,0 +1,105 @@
+use cpal::FromSample;
+
+use crate::simd;
+
+// Sample types the renderers take. Values are converted to f32 in [-1, 1]
+// the way cpal's `FromSample` does, so i16, u8 or i32 PCM gives the same
+// picture as the equivalent f32 samples.
+pub trait PcmSample: Copy + Sync {
+    fn to_f32(self) -> f32;
+
+    // Converts as many samples as both slices hold. Types with a faster bulk
+    // conversion override it; the result must equal `to_f32` on each sample.
+    fn convert(samples: &[Self], out: &mut [f32]) {
+        for (converted, &s) in out.iter_mut().zip(samples) {
+            *converted = s.to_f32();
+        }
+    }
+}
+
+macro_rules! pcm_sample {
+    ($($sample:ty),*) => {
+        $(
+            impl PcmSample for $sample {
+                fn to_f32(self) -> f32 {
+                    f32::from_sample_(self)
+                }
+            }
+        )*
+    };
+}
+
+pcm_sample!(i8, i32, i64, u8, u16, u32, u64, f32, f64);
+
+impl PcmSample for i16 {
+    fn to_f32(self) -> f32 {
+        f32::from_sample_(self)
+    }
+
+    fn convert(samples: &[Self], out: &mut [f32]) {
+        simd::i16_to_f32(samples, out);
+    }
+}
+
+// 24-bit little-endian PCM as it is stored in WAV files: three bytes per
+// sample, no padding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct PackedI24(pub [u8; 3]);
+
+impl PackedI24 {
+    const SCALE: f32 = 8_388_608.0;
+
+    // Views a byte buffer as samples without copying it. Trailing bytes that
+    // do not make a whole sample are left out.
+    pub fn from_bytes(bytes: &[u8]) -> &[PackedI24] {
+        let len = bytes.len() / 3;
+        // SAFETY: `PackedI24` is a transparent wrapper around `[u8; 3]`, so it
+        // has the size of three bytes and an alignment of one.
+        unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const PackedI24, len) }
+    }
+
+    fn as_bytes(samples: &[PackedI24]) -> &[u8] {
+        // SAFETY: see `from_bytes`; every `PackedI24` is three initialized
+        // bytes.
+        unsafe { std::slice::from_raw_parts(samples.as_ptr() as *const u8, samples.len() * 3) }
+    }
+
+    pub fn to_i32(self) -> i32 {
+        let [low, middle, high] = self.0;
+        i32::from_le_bytes([0, low, middle, high]) >> 8
+    }
+}
+
+impl FromSample<PackedI24> for f32 {
+    fn from_sample_(s: PackedI24) -> Self {
+        s.to_i32() as f32 / PackedI24::SCALE
+    }
+}
+
+impl PcmSample for PackedI24 {
+    fn to_f32(self) -> f32 {
+        f32::from_sample_(self)
+    }
+
+    // Each sample but the last is read with one 4-byte load that reaches
+    // into the next sample; the extra byte is shifted out.
+    fn convert(samples: &[Self], out: &mut [f32]) {
+        let len = samples.len().min(out.len());
+        let Some(last) = len.checked_sub(1) else {
+            return;
+        };
+
+        let bytes = PackedI24::as_bytes(&samples[..len]);
+        for (k, converted) in out[..last].iter_mut().enumerate() {
+            let word = u32::from_le_bytes([
+                bytes[3 * k],
+                bytes[3 * k + 1],
+                bytes[3 * k + 2],
+                bytes[3 * k + 3],
+            ]);
+            *converted = (((word << 8) as i32) >> 8) as f32 / PackedI24::SCALE;
+        }
+        out[last] = samples[last].to_f32();
+    }
+}
@@ -0,0 +1,309 @@
+// Reduction kernels for the hot loops over f32 samples, and the i16 to f32
+// conversion feeding them, with AVX2 and SSE2 versions picked at runtime on
+// x86_64. Every version gives bit-identical results: min and max do not
+// depend on order, sums of squares are kept in four f64 lanes (sample i goes
+// to lane i % 4) whatever the vector width, and the conversion is exact.
+
+pub(crate) const SUM_LANES: usize = 4;
+
+// Full scale of i16 PCM. Dividing by a power of two is exact, so multiplying
+// by its inverse gives the same floats.
+const I16_SCALE: f32 = 32768.0;
+
+pub(crate) fn abs_peak(samples: &[f32]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { x86::abs_peak_avx2(samples) };
+        }
+        if is_x86_feature_detected!("sse2") {
+            // SAFETY: SSE2 support was just checked.
+            return unsafe { x86::abs_peak_sse2(samples) };
+        }
+    }
+
+    scalar::abs_peak(samples)
+}
+
+// `min` and `max` lowered/raised by the samples. NaNs are ignored.
+pub(crate) fn min_max(samples: &[f32], min: f32, max: f32) -> (f32, f32) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { x86::min_max_avx2(samples, min, max) };
+        }
+        if is_x86_feature_detected!("sse2") {
+            // SAFETY: SSE2 support was just checked.
+            return unsafe { x86::min_max_sse2(samples, min, max) };
+        }
+    }
+
+    scalar::min_max(samples, min, max)
+}
+
+// Adds the squares of `samples` to `lanes`, the first sample going to lane
+// 0. Only whole groups of `SUM_LANES` samples are taken; the number of
+// samples consumed is returned.
+pub(crate) fn sum_squares(samples: &[f32], lanes: &mut [f64; SUM_LANES]) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { x86::sum_squares_avx2(samples, lanes) };
+        }
+        if is_x86_feature_detected!("sse2") {
+            // SAFETY: SSE2 support was just checked.
+            return unsafe { x86::sum_squares_sse2(samples, lanes) };
+        }
+    }
+
+    scalar::sum_squares(samples, lanes)
+}
+
+// `out[i] = samples[i] / 32768` for as many samples as both slices hold.
+pub(crate) fn i16_to_f32(samples: &[i16], out: &mut [f32]) {
+    let len = samples.len().min(out.len());
+    let (samples, out) = (&samples[..len], &mut out[..len]);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { x86::i16_to_f32_avx2(samples, out) };
+        }
+        if is_x86_feature_detected!("sse2") {
+            // SAFETY: SSE2 support was just checked.
+            return unsafe { x86::i16_to_f32_sse2(samples, out) };
+        }
+    }
+
+    scalar::i16_to_f32(samples, out)
+}
+
+pub(crate) fn lane_total(lanes: &[f64; SUM_LANES]) -> f64 {
+    (lanes[0] + lanes[1]) + (lanes[2] + lanes[3])
+}
+
+pub(crate) mod scalar {
+    use super::{I16_SCALE, SUM_LANES};
+
+    pub(crate) fn abs_peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    pub(crate) fn min_max(samples: &[f32], min: f32, max: f32) -> (f32, f32) {
+        samples
+            .iter()
+            .fold((min, max), |(min, max), &s| (min.min(s), max.max(s)))
+    }
+
+    pub(crate) fn sum_squares(samples: &[f32], lanes: &mut [f64; SUM_LANES]) -> usize {
+        let groups = samples.chunks_exact(SUM_LANES);
+        let consumed = samples.len() - groups.remainder().len();
+
+        for group in groups {
+            for (lane, &s) in lanes.iter_mut().zip(group) {
+                *lane += (s as f64) * (s as f64);
+            }
+        }
+
+        consumed
+    }
+
+    pub(crate) fn i16_to_f32(samples: &[i16], out: &mut [f32]) {
+        for (converted, &s) in out.iter_mut().zip(samples) {
+            *converted = s as f32 / I16_SCALE;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::{scalar, I16_SCALE, SUM_LANES};
+
+    // `_mm*_max_ps(v, acc)` returns `acc` when `v` is NaN, like `f32::max`.
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn abs_peak_avx2(samples: &[f32]) -> f32 {
+        let sign = _mm256_set1_ps(-0.0);
+        let mut peak = _mm256_setzero_ps();
+        let chunks = samples.chunks_exact(8);
+        let tail = chunks.remainder();
+        for chunk in chunks {
+            let v = _mm256_andnot_ps(sign, _mm256_loadu_ps(chunk.as_ptr()));
+            peak = _mm256_max_ps(v, peak);
+        }
+
+        let mut lanes = [0.0f32; 8];
+        _mm256_storeu_ps(lanes.as_mut_ptr(), peak);
+        scalar::abs_peak(&lanes).max(scalar::abs_peak(tail))
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn abs_peak_sse2(samples: &[f32]) -> f32 {
+        let sign = _mm_set1_ps(-0.0);
+        let mut peak = _mm_setzero_ps();
+        let chunks = samples.chunks_exact(4);
+        let tail = chunks.remainder();
+        for chunk in chunks {
+            let v = _mm_andnot_ps(sign, _mm_loadu_ps(chunk.as_ptr()));
+            peak = _mm_max_ps(v, peak);
+        }
+
+        let mut lanes = [0.0f32; 4];
+        _mm_storeu_ps(lanes.as_mut_ptr(), peak);
+        scalar::abs_peak(&lanes).max(scalar::abs_peak(tail))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn min_max_avx2(samples: &[f32], min: f32, max: f32) -> (f32, f32) {
+        let mut low = _mm256_set1_ps(min);
+        let mut high = _mm256_set1_ps(max);
+        let chunks = samples.chunks_exact(8);
+        let tail = chunks.remainder();
+        for chunk in chunks {
+            let v = _mm256_loadu_ps(chunk.as_ptr());
+            low = _mm256_min_ps(v, low);
+            high = _mm256_max_ps(v, high);
+        }
+
+        let (mut lows, mut highs) = ([0.0f32; 8], [0.0f32; 8]);
+        _mm256_storeu_ps(lows.as_mut_ptr(), low);
+        _mm256_storeu_ps(highs.as_mut_ptr(), high);
+        let (min, _) = scalar::min_max(&lows, min, max);
+        let (_, max) = scalar::min_max(&highs, min, max);
+        scalar::min_max(tail, min, max)
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn min_max_sse2(samples: &[f32], min: f32, max: f32) -> (f32, f32) {
+        let mut low = _mm_set1_ps(min);
+        let mut high = _mm_set1_ps(max);
+        let chunks = samples.chunks_exact(4);
+        let tail = chunks.remainder();
+        for chunk in chunks {
+            let v = _mm_loadu_ps(chunk.as_ptr());
+            low = _mm_min_ps(v, low);
+            high = _mm_max_ps(v, high);
+        }
+
+        let (mut lows, mut highs) = ([0.0f32; 4], [0.0f32; 4]);
+        _mm_storeu_ps(lows.as_mut_ptr(), low);
+        _mm_storeu_ps(highs.as_mut_ptr(), high);
+        let (min, _) = scalar::min_max(&lows, min, max);
+        let (_, max) = scalar::min_max(&highs, min, max);
+        scalar::min_max(tail, min, max)
+    }
+
+    // Squares of f32 values are exact in f64, so only the additions round,
+    // and they happen in the same order as in `scalar::sum_squares`.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn sum_squares_avx2(samples: &[f32], lanes: &mut [f64; SUM_LANES]) -> usize {
+        let mut sum = _mm256_loadu_pd(lanes.as_ptr());
+        let groups = samples.chunks_exact(SUM_LANES);
+        let consumed = samples.len() - groups.remainder().len();
+        for group in groups {
+            let v = _mm256_cvtps_pd(_mm_loadu_ps(group.as_ptr()));
+            sum = _mm256_add_pd(sum, _mm256_mul_pd(v, v));
+        }
+        _mm256_storeu_pd(lanes.as_mut_ptr(), sum);
+
+        consumed
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn sum_squares_sse2(samples: &[f32], lanes: &mut [f64; SUM_LANES]) -> usize {
+        let mut low = _mm_loadu_pd(lanes.as_ptr());
+        let mut high = _mm_loadu_pd(lanes.as_ptr().add(2));
+        let groups = samples.chunks_exact(SUM_LANES);
+        let consumed = samples.len() - groups.remainder().len();
+        for group in groups {
+            let v = _mm_loadu_ps(group.as_ptr());
+            let first = _mm_cvtps_pd(v);
+            let second = _mm_cvtps_pd(_mm_movehl_ps(v, v));
+            low = _mm_add_pd(low, _mm_mul_pd(first, first));
+            high = _mm_add_pd(high, _mm_mul_pd(second, second));
+        }
+        _mm_storeu_pd(lanes.as_mut_ptr(), low);
+        _mm_storeu_pd(lanes.as_mut_ptr().add(2), high);
+
+        consumed
+    }
+
+    // `samples` and `out` have the same length.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn i16_to_f32_avx2(samples: &[i16], out: &mut [f32]) {
+        let scale = _mm256_set1_ps(1.0 / I16_SCALE);
+        let chunks = samples.chunks_exact(8);
+        let done = samples.len() - chunks.remainder().len();
+        for (i, chunk) in chunks.enumerate() {
+            let v = _mm256_cvtepi16_epi32(_mm_loadu_si128(chunk.as_ptr() as *const __m128i));
+            let converted = _mm256_mul_ps(_mm256_cvtepi32_ps(v), scale);
+            _mm256_storeu_ps(out.as_mut_ptr().add(i * 8), converted);
+        }
+
+        scalar::i16_to_f32(&samples[done..], &mut out[done..]);
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn i16_to_f32_sse2(samples: &[i16], out: &mut [f32]) {
+        let scale = _mm_set1_ps(1.0 / I16_SCALE);
+        let chunks = samples.chunks_exact(8);
+        let done = samples.len() - chunks.remainder().len();
+        for (i, chunk) in chunks.enumerate() {
+            let v = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            // Each i16 into the high half of an i32, then shifted back down
+            // with its sign.
+            let low = _mm_srai_epi32(_mm_unpacklo_epi16(v, v), 16);
+            let high = _mm_srai_epi32(_mm_unpackhi_epi16(v, v), 16);
+            let target = out.as_mut_ptr().add(i * 8);
+            _mm_storeu_ps(target, _mm_mul_ps(_mm_cvtepi32_ps(low), scale));
+            _mm_storeu_ps(target.add(4), _mm_mul_ps(_mm_cvtepi32_ps(high), scale));
+        }
+
+        scalar::i16_to_f32(&samples[done..], &mut out[done..]);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    // xorshift32, enough to spread test inputs without a dependency.
+    pub(crate) fn random_samples(seed: &mut u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 17;
+                *seed ^= *seed << 5;
+                (*seed as f32 / u32::MAX as f32) * 4.0 - 2.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn simd_kernels_match_the_scalar_ones() {
+        let mut seed = 0x9e37_79b9;
+        for round in 0..200 {
+            let len = (seed as usize % 300) + round % 3;
+            let samples = random_samples(&mut seed, len);
+
+            assert_eq!(abs_peak(&samples), scalar::abs_peak(&samples));
+            assert_eq!(
+                min_max(&samples, f32::MAX, f32::MIN),
+                scalar::min_max(&samples, f32::MAX, f32::MIN)
+            );
+
+            let (mut lanes, mut scalar_lanes) = ([0.5; 4], [0.5; 4]);
+            assert_eq!(
+                sum_squares(&samples, &mut lanes),
+                scalar::sum_squares(&samples, &mut scalar_lanes)
+            );
+            assert_eq!(lanes, scalar_lanes);
+        }
+    }
+}
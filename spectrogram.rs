@@ -0,0 +1,266 @@
+use std::f32::consts::PI;
+
+use imageproc::image::Rgba;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+
+use crate::core::audio_process::column_range;
+use crate::core::{blank_image, MySample, ViewSignal};
+use crate::error::Error;
+use crate::sample::PcmSample;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowFunction {
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    Rectangular,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrequencyScale {
+    Linear,
+    #[default]
+    Log,
+    Mel,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Magma,
+    Grayscale,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrogramStyle {
+    pub size: [usize; 2],
+    pub window: WindowFunction,
+    pub fft_size: usize,
+    pub hop: usize,
+    // [floor, ceiling] in dBFS, mapped onto the two ends of the colormap.
+    pub db_range: [f32; 2],
+    pub frequency_scale: FrequencyScale,
+    // Lowest frequency drawn by the log scale, in Hz.
+    pub min_frequency: f32,
+    pub colormap: Colormap,
+}
+
+impl Default for SpectrogramStyle {
+    fn default() -> Self {
+        SpectrogramStyle {
+            size: [1000, 400],
+            window: WindowFunction::default(),
+            fft_size: 2048,
+            hop: 512,
+            db_range: [-100.0, 0.0],
+            frequency_scale: FrequencyScale::default(),
+            min_frequency: 20.0,
+            colormap: Colormap::default(),
+        }
+    }
+}
+
+impl ViewSignal {
+    // Short-time Fourier transform of the mono mix of `sound`. Columns that
+    // cover several STFT frames keep the loudest value of each bin.
+    pub fn try_spectrogram<T: PcmSample>(
+        sound: &[T],
+        channels: usize,
+        sample_rate: u32,
+        style: &SpectrogramStyle,
+    ) -> Result<Self, Error> {
+        if style.fft_size < 2 || style.hop == 0 {
+            return Err(Error::InvalidSettings(format!(
+                "fft size {} and hop {}",
+                style.fft_size, style.hop
+            )));
+        }
+        if style.db_range[0].partial_cmp(&style.db_range[1]) != Some(std::cmp::Ordering::Less) {
+            return Err(Error::InvalidSettings(format!(
+                "dB range {:?}",
+                style.db_range
+            )));
+        }
+
+        let mut image = blank_image(style.size, [0, 0, 0, 255])?;
+        let [width, height] = style.size;
+
+        let channels = channels.max(1);
+        let frames = sound.len() / channels;
+        let mono = |i: usize| -> f32 {
+            let frame = &sound[i * channels..(i + 1) * channels];
+            let sum: f32 = frame.iter().map(|s| s.to_f32()).sum();
+            sum / channels as f32
+        };
+
+        let fft_size = style.fft_size;
+        let window = window_coefficients(style.window, fft_size);
+        let window_sum: f32 = window.iter().sum();
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(fft_size);
+        let mut buffer = vec![Complex::new(0.0, 0.0); fft_size];
+        let bins = fft_size / 2 + 1;
+
+        let stft_frames = frames.saturating_sub(fft_size) / style.hop + 1;
+        let rows = row_bins(style, sample_rate, bins);
+
+        let mut column_db = vec![f32::NEG_INFINITY; bins];
+        for x in 0..width {
+            column_db.fill(f32::NEG_INFINITY);
+
+            for frame in column_range(x, width, stft_frames) {
+                let offset = frame * style.hop;
+                for (i, (slot, w)) in buffer.iter_mut().zip(&window).enumerate() {
+                    let s = if offset + i < frames {
+                        mono(offset + i)
+                    } else {
+                        0.0
+                    };
+                    *slot = Complex::new(s * w, 0.0);
+                }
+                fft.process(&mut buffer);
+
+                for (db, bin) in column_db.iter_mut().zip(&buffer[..bins]) {
+                    let amplitude = 2.0 * bin.norm() / window_sum;
+                    *db = db.max(20.0 * amplitude.max(1e-10).log10());
+                }
+            }
+
+            for (y, bins) in rows.iter().enumerate() {
+                let db = column_db[bins.clone()]
+                    .iter()
+                    .fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+                let level = (db - style.db_range[0]) / (style.db_range[1] - style.db_range[0]);
+                image.put_pixel(x as u32, y as u32, colormap(style.colormap, level));
+            }
+        }
+
+        debug_assert_eq!(rows.len(), height);
+
+        Ok(ViewSignal::from_image(image))
+    }
+
+    pub fn try_spectrogram_from_sample(
+        sample: &MySample,
+        style: &SpectrogramStyle,
+    ) -> Result<Self, Error> {
+        Self::try_spectrogram(
+            &sample.samples,
+            sample.channels as usize,
+            sample.sample_rate,
+            style,
+        )
+    }
+}
+
+fn window_coefficients(window: WindowFunction, size: usize) -> Vec<f32> {
+    let n = (size - 1) as f32;
+
+    (0..size)
+        .map(|i| {
+            let phase = 2.0 * PI * i as f32 / n;
+            match window {
+                WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+                WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+                WindowFunction::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos(),
+                WindowFunction::Rectangular => 1.0,
+            }
+        })
+        .collect()
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+// FFT bins covered by each image row, top row first (highest frequencies).
+fn row_bins(
+    style: &SpectrogramStyle,
+    sample_rate: u32,
+    bins: usize,
+) -> Vec<std::ops::Range<usize>> {
+    let height = style.size[1];
+    let nyquist = sample_rate as f32 / 2.0;
+    let bin_width = sample_rate as f32 / style.fft_size as f32;
+    let min_frequency = style.min_frequency.clamp(1.0, nyquist.max(1.0));
+
+    // Frequency at `t` of the way from the bottom edge to the top edge.
+    let frequency = |t: f32| -> f32 {
+        match style.frequency_scale {
+            FrequencyScale::Linear => t * nyquist,
+            FrequencyScale::Log => min_frequency * (nyquist / min_frequency).powf(t),
+            FrequencyScale::Mel => mel_to_hz(t * hz_to_mel(nyquist)),
+        }
+    };
+
+    (0..height)
+        .map(|y| {
+            let high = frequency(1.0 - y as f32 / height as f32);
+            let low = frequency(1.0 - (y + 1) as f32 / height as f32);
+
+            let first = ((low / bin_width).round() as usize).min(bins - 1);
+            let last = ((high / bin_width).round() as usize).clamp(first, bins - 1);
+            first..last + 1
+        })
+        .collect()
+}
+
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+// `level` in [0, 1]; values outside are clamped.
+pub(crate) fn colormap(map: Colormap, level: f32) -> Rgba<u8> {
+    let level = if level.is_nan() {
+        0.0
+    } else {
+        level.clamp(0.0, 1.0)
+    };
+
+    let stops: &[[u8; 3]] = match map {
+        Colormap::Viridis => &VIRIDIS,
+        Colormap::Magma => &MAGMA,
+        Colormap::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+    };
+
+    let position = level * (stops.len() - 1) as f32;
+    let i = (position.floor() as usize).min(stops.len() - 2);
+    let t = position - i as f32;
+
+    let mut rgba = [0, 0, 0, 255];
+    for c in 0..3 {
+        let from = stops[i][c] as f32;
+        let to = stops[i + 1][c] as f32;
+        rgba[c] = (from + (to - from) * t).round() as u8;
+    }
+
+    Rgba(rgba)
+}
@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rodio::{Decoder, Source};
+
+use crate::color::{CentroidAccumulator, ColorMode};
+use crate::core::audio_process::{lane_area, EnvelopeAccumulator, Lane};
+use crate::core::{image_len, ChannelMode, ViewSignal};
+use crate::error::Error;
+use crate::normalization::LevelMeter;
+use crate::style::WaveformStyle;
+
+// Renders a waveform from interleaved samples pushed in order. Only the
+// current frame and one column per lane are kept, so memory does not grow
+// with the length of the sound. The image is identical to the one
+// `ViewSignal::try_with_style` gives for the same samples.
+pub struct WaveformStream {
+    style: WaveformStyle,
+    channels: usize,
+    meter: LevelMeter,
+    accumulators: Vec<EnvelopeAccumulator>,
+    // One per lane with `ColorMode::SpectralCentroid`, none otherwise.
+    centroids: Vec<CentroidAccumulator>,
+    frame: Vec<f32>,
+}
+
+impl WaveformStream {
+    // `total_frames` decides the column boundaries and must be known first.
+    pub fn new(
+        style: &WaveformStyle,
+        channels: usize,
+        sample_rate: u32,
+        total_frames: usize,
+    ) -> Self {
+        Self::try_new(style, channels, sample_rate, total_frames).unwrap()
+    }
+
+    // Fails on a size `finish` couldn't draw, before a column is allocated.
+    pub fn try_new(
+        style: &WaveformStyle,
+        channels: usize,
+        sample_rate: u32,
+        total_frames: usize,
+    ) -> Result<Self, Error> {
+        image_len(style.size)?;
+        let channels = channels.max(1);
+        let lanes = match style.channel_mode {
+            ChannelMode::MixDown => 1,
+            ChannelMode::Lanes | ChannelMode::Overlay => channels,
+        };
+
+        Ok(WaveformStream {
+            style: style.clone(),
+            channels,
+            meter: LevelMeter::new(style.normalization, channels, sample_rate),
+            accumulators: (0..lanes)
+                .map(|_| EnvelopeAccumulator::new(style.size[0], total_frames))
+                .collect(),
+            centroids: match style.color_mode {
+                ColorMode::SpectralCentroid => (0..lanes)
+                    .map(|_| CentroidAccumulator::new(style.size[0], total_frames, sample_rate))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            frame: Vec::with_capacity(channels),
+        })
+    }
+
+    pub fn push_sample(&mut self, s: f32) {
+        self.frame.push(s);
+
+        if self.frame.len() == self.channels {
+            self.push_frame();
+        }
+    }
+
+    pub fn push_interleaved(&mut self, samples: &[f32]) {
+        for &s in samples {
+            self.push_sample(s);
+        }
+    }
+
+    fn push_frame(&mut self) {
+        self.meter.push_frame(self.frame.iter().copied());
+
+        match self.style.channel_mode {
+            ChannelMode::MixDown => {
+                let sum: f32 = self.frame.iter().copied().sum();
+                let s = sum / self.frame.len() as f32;
+                self.accumulators[0].push(s);
+                if let Some(centroid) = self.centroids.first_mut() {
+                    centroid.push(s);
+                }
+            }
+            ChannelMode::Lanes | ChannelMode::Overlay => {
+                for (accumulator, s) in self.accumulators.iter_mut().zip(&self.frame) {
+                    accumulator.push(*s);
+                }
+                for (centroid, s) in self.centroids.iter_mut().zip(&self.frame) {
+                    centroid.push(*s);
+                }
+            }
+        }
+
+        self.frame.clear();
+    }
+
+    pub fn finish(self) -> Result<ViewSignal, Error> {
+        let wave_ratio = self.style.normalization.gain_for(&self.meter.finish());
+
+        let mut centroids = self.centroids.into_iter();
+        let lanes: Vec<Lane> = self
+            .accumulators
+            .into_iter()
+            .enumerate()
+            .map(|(c, accumulator)| Lane {
+                area: lane_area(&self.style, self.channels, c),
+                envelope: accumulator.finish(),
+                centroids: centroids.next().map(|c| c.finish()).unwrap_or_default(),
+            })
+            .collect();
+
+        ViewSignal::render_lanes(lanes, wave_ratio, &self.style)
+    }
+}
+
+impl ViewSignal {
+    // Decodes `reader` twice: a first pass counts the frames, the second one
+    // feeds a `WaveformStream`. No decoded samples are kept in between.
+    pub fn try_stream<R: Read + Seek + Send + 'static>(
+        reader: R,
+        style: &WaveformStyle,
+    ) -> Result<Self, Error> {
+        let reader = SharedReader::new(reader)?;
+
+        let source = Decoder::new(reader.clone())?;
+        let channels = source.channels() as usize;
+        let sample_rate = source.sample_rate();
+        let total_frames = source.count() / channels.max(1);
+
+        reader.rewind()?;
+        let source = Decoder::new(reader)?;
+
+        let mut stream = WaveformStream::try_new(style, channels, sample_rate, total_frames)?;
+        for s in source.convert_samples::<f32>() {
+            stream.push_sample(s);
+        }
+
+        stream.finish()
+    }
+
+    pub fn try_stream_file(file_path: &str, style: &WaveformStyle) -> Result<Self, Error> {
+        Self::try_stream(BufReader::new(File::open(file_path)?), style)
+    }
+}
+
+// Lets two decoders read the same source one after the other. Positions are
+// relative to where the reader stood when it was handed over.
+pub(crate) struct SharedReader<R> {
+    inner: Arc<Mutex<R>>,
+    start: u64,
+}
+
+impl<R> Clone for SharedReader<R> {
+    fn clone(&self) -> Self {
+        SharedReader {
+            inner: Arc::clone(&self.inner),
+            start: self.start,
+        }
+    }
+}
+
+impl<R: Seek> SharedReader<R> {
+    pub(crate) fn new(mut reader: R) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+
+        Ok(SharedReader {
+            inner: Arc::new(Mutex::new(reader)),
+            start,
+        })
+    }
+
+    pub(crate) fn rewind(&self) -> io::Result<()> {
+        self.lock()?.seek(SeekFrom::Start(self.start))?;
+
+        Ok(())
+    }
+}
+
+impl<R> SharedReader<R> {
+    fn lock(&self) -> io::Result<MutexGuard<'_, R>> {
+        self.inner
+            .lock()
+            .map_err(|_| io::Error::other("shared reader lock poisoned"))
+    }
+}
+
+impl<R: Read> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock()?.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for SharedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(self.start + offset),
+            other => other,
+        };
+        let position = self.lock()?.seek(pos)?;
+
+        Ok(position.saturating_sub(self.start))
+    }
+}
@@ -0,0 +1,333 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::ColorMode;
+use crate::core::{ChannelMode, MySample, ViewSignal};
+use crate::error::Error;
+use crate::normalization::{Normalization, DEFAULT_SAMPLE_RATE};
+use crate::sample::PcmSample;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderMode {
+    // Span from the lowest to the highest sample of each column.
+    #[default]
+    Peak,
+    // Span of +/- the RMS level of each column.
+    Rms,
+}
+
+// How an amplitude maps to a height, as a fraction of the half lane.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum AmplitudeAxis {
+    #[default]
+    Linear,
+    // `floor_db` dBFS and below sit on the center line, 0 dBFS on the edge.
+    Decibel {
+        floor_db: f32,
+    },
+    // `blend` of the decibel height plus the rest of the linear one.
+    Hybrid {
+        floor_db: f32,
+        blend: f32,
+    },
+}
+
+impl AmplitudeAxis {
+    // Keeps the sign of `amplitude`; the result is in [-1, 1] for input in
+    // [-1, 1].
+    pub fn scale(&self, amplitude: f32) -> f32 {
+        match *self {
+            AmplitudeAxis::Linear => amplitude,
+            AmplitudeAxis::Decibel { floor_db } => {
+                let floor_db = floor_db.min(-f32::EPSILON);
+                let db = 20.0 * amplitude.abs().max(1e-10).log10();
+                amplitude.signum() * ((db - floor_db) / -floor_db).max(0.0)
+            }
+            AmplitudeAxis::Hybrid { floor_db, blend } => {
+                let blend = blend.clamp(0.0, 1.0);
+                let decibel = AmplitudeAxis::Decibel { floor_db }.scale(amplitude);
+                blend * decibel + (1.0 - blend) * amplitude
+            }
+        }
+    }
+
+    // Amplitude drawn at `height`, the inverse of `scale`.
+    pub fn unscale(&self, height: f32) -> f32 {
+        match *self {
+            AmplitudeAxis::Linear => height,
+            AmplitudeAxis::Decibel { floor_db } => {
+                let floor_db = floor_db.min(-f32::EPSILON);
+                let db = floor_db - height.abs() * floor_db;
+                height.signum() * 10f32.powf(db / 20.0)
+            }
+            AmplitudeAxis::Hybrid { .. } => {
+                // Monotonic, so a bisection is enough.
+                let (mut low, mut high) = (0.0f32, height.abs().max(1.0));
+                for _ in 0..40 {
+                    let middle = (low + high) / 2.0;
+                    if self.scale(middle) < height.abs() {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                height.signum() * (low + high) / 2.0
+            }
+        }
+    }
+}
+
+// Shape drawn from the per-column envelope.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderStyle {
+    // One span per column from its low to its high point.
+    #[default]
+    Filled,
+    // Spans of +/- the larger of the low and high point.
+    Mirrored,
+    // Outline through the low and high points only.
+    Line,
+    // Mirrored amplitude drawn upwards from the bottom of the lane.
+    HalfWave,
+    // Columns grouped into bars `width` wide, `gap` apart, with rounded ends.
+    Bars {
+        width: usize,
+        gap: usize,
+        radius: usize,
+    },
+    // A dot on each edge of the envelope every `2 * radius + gap` columns.
+    Dots {
+        radius: usize,
+        gap: usize,
+    },
+}
+
+impl RenderStyle {
+    // Columns from one bar or dot to the next. Like the image size, it has to
+    // fit a u32 so the drawing arithmetic can't overflow.
+    pub(crate) fn check_step(&self) -> Result<(), Error> {
+        let step = match *self {
+            RenderStyle::Bars { width, gap, .. } => width.max(1).checked_add(gap),
+            RenderStyle::Dots { radius, gap } => radius
+                .max(1)
+                .checked_mul(2)
+                .and_then(|diameter| diameter.checked_add(gap)),
+            _ => Some(0),
+        };
+
+        match step {
+            Some(step) if step <= u32::MAX as usize => Ok(()),
+            _ => Err(Error::InvalidSettings(format!(
+                "{self:?} shapes are too far apart"
+            ))),
+        }
+    }
+}
+
+// Everything that decides how a waveform looks. Missing fields take their
+// default value when a preset is deserialized.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaveformStyle {
+    pub size: [usize; 2],
+    // RGBA; a background alpha below 255 gives a (semi-)transparent image.
+    pub wave_color: [u8; 4],
+    // Wave color left of the playhead, see `ViewSignal::set_playhead`.
+    pub played_color: [u8; 4],
+    pub background_color: [u8; 4],
+    pub line_thickness: usize,
+    pub vertical_padding: usize,
+    pub show_baseline: bool,
+    pub antialiasing: bool,
+    pub amplitude_scale: f32,
+    pub render_mode: RenderMode,
+    pub amplitude_axis: AmplitudeAxis,
+    pub render_style: RenderStyle,
+    pub color_mode: ColorMode,
+    pub channel_mode: ChannelMode,
+    pub normalization: Normalization,
+}
+
+impl Default for WaveformStyle {
+    fn default() -> Self {
+        WaveformStyle {
+            size: [1000, 200],
+            wave_color: [0, 0, 0, 255],
+            played_color: [255, 85, 0, 255],
+            background_color: [255, 255, 255, 255],
+            line_thickness: 1,
+            vertical_padding: 0,
+            show_baseline: false,
+            antialiasing: true,
+            amplitude_scale: 1.0,
+            render_mode: RenderMode::default(),
+            amplitude_axis: AmplitudeAxis::default(),
+            render_style: RenderStyle::default(),
+            color_mode: ColorMode::default(),
+            channel_mode: ChannelMode::default(),
+            normalization: Normalization::default(),
+        }
+    }
+}
+
+pub fn opaque(color: [u8; 3]) -> [u8; 4] {
+    [color[0], color[1], color[2], 255]
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ViewSignalBuilder {
+    style: WaveformStyle,
+    pixels_per_second: Option<f64>,
+}
+
+impl ViewSignalBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn style(mut self, style: WaveformStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn size(mut self, desired_size: [usize; 2]) -> Self {
+        self.style.size = desired_size;
+        self
+    }
+
+    pub fn wave_color(mut self, wave_color: [u8; 3]) -> Self {
+        self.style.wave_color = opaque(wave_color);
+        self
+    }
+
+    pub fn wave_rgba(mut self, wave_color: [u8; 4]) -> Self {
+        self.style.wave_color = wave_color;
+        self
+    }
+
+    pub fn played_color(mut self, played_color: [u8; 4]) -> Self {
+        self.style.played_color = played_color;
+        self
+    }
+
+    pub fn background_color(mut self, background_color: [u8; 3]) -> Self {
+        self.style.background_color = opaque(background_color);
+        self
+    }
+
+    pub fn background_rgba(mut self, background_color: [u8; 4]) -> Self {
+        self.style.background_color = background_color;
+        self
+    }
+
+    pub fn transparent_background(self) -> Self {
+        self.background_rgba([0, 0, 0, 0])
+    }
+
+    pub fn line_thickness(mut self, line_thickness: usize) -> Self {
+        self.style.line_thickness = line_thickness;
+        self
+    }
+
+    pub fn vertical_padding(mut self, vertical_padding: usize) -> Self {
+        self.style.vertical_padding = vertical_padding;
+        self
+    }
+
+    pub fn baseline(mut self, show_baseline: bool) -> Self {
+        self.style.show_baseline = show_baseline;
+        self
+    }
+
+    pub fn antialiasing(mut self, antialiasing: bool) -> Self {
+        self.style.antialiasing = antialiasing;
+        self
+    }
+
+    pub fn amplitude_scale(mut self, amplitude_scale: f32) -> Self {
+        self.style.amplitude_scale = amplitude_scale;
+        self
+    }
+
+    pub fn render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.style.render_mode = render_mode;
+        self
+    }
+
+    pub fn amplitude_axis(mut self, amplitude_axis: AmplitudeAxis) -> Self {
+        self.style.amplitude_axis = amplitude_axis;
+        self
+    }
+
+    pub fn render_style(mut self, render_style: RenderStyle) -> Self {
+        self.style.render_style = render_style;
+        self
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.style.color_mode = color_mode;
+        self
+    }
+
+    pub fn channel_mode(mut self, channel_mode: ChannelMode) -> Self {
+        self.style.channel_mode = channel_mode;
+        self
+    }
+
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.style.normalization = normalization;
+        self
+    }
+
+    // Width follows the duration of the sound instead of `size[0]` when
+    // building from a `MySample`.
+    pub fn pixels_per_second(mut self, pixels_per_second: f64) -> Self {
+        self.pixels_per_second = Some(pixels_per_second);
+        self
+    }
+
+    pub fn get_style(&self) -> &WaveformStyle {
+        &self.style
+    }
+
+    pub fn build<T: PcmSample>(&self, sound: &[T]) -> Result<ViewSignal, Error> {
+        ViewSignal::try_with_style(sound, 1, DEFAULT_SAMPLE_RATE, &self.style)
+    }
+
+    pub fn build_interleaved<T: PcmSample>(
+        &self,
+        sound: &[T],
+        channels: usize,
+        sample_rate: u32,
+    ) -> Result<ViewSignal, Error> {
+        ViewSignal::try_with_style(sound, channels, sample_rate, &self.style)
+    }
+
+    pub fn build_range(
+        &self,
+        sample: &MySample,
+        range: Range<Duration>,
+    ) -> Result<ViewSignal, Error> {
+        let style = self.style_for(sample, range.end.saturating_sub(range.start));
+        ViewSignal::try_from_sample_range(sample, range, &style)
+    }
+
+    pub fn build_from_sample(&self, sample: &MySample) -> Result<ViewSignal, Error> {
+        ViewSignal::try_with_style(
+            &sample.samples,
+            sample.channels as usize,
+            sample.sample_rate,
+            &self.style_for(sample, sample.duration),
+        )
+    }
+
+    fn style_for(&self, sample: &MySample, duration: Duration) -> WaveformStyle {
+        let mut style = self.style.clone();
+        if let Some(pixels_per_second) = self.pixels_per_second {
+            style.size[0] = sample.layout(pixels_per_second).width(duration);
+        }
+        style
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::color::ColorMode;
+use crate::core::audio_process::{lane_envelopes, LaneGeometry};
+use crate::core::{image_len, MySample};
+use crate::error::Error;
+use crate::sample::PcmSample;
+use crate::style::{RenderStyle, WaveformStyle};
+
+const TIME_AXIS_TICKS: usize = 10;
+const TIME_AXIS_FONT_SIZE: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SvgOptions {
+    // Adds a viewBox so the drawing scales with the element it is put in.
+    pub view_box: bool,
+    // Tick marks and mm:ss.ms labels along the bottom edge.
+    pub time_axis: bool,
+}
+
+// Vector counterpart of `ViewSignal`: the same per-column envelope, written
+// as one SVG path outline per lane.
+pub struct SvgSignal {
+    document: String,
+}
+
+impl SvgSignal {
+    pub fn try_with_style<T: PcmSample>(
+        sound: &[T],
+        channels: usize,
+        sample_rate: u32,
+        style: &WaveformStyle,
+        options: SvgOptions,
+    ) -> Result<Self, Error> {
+        image_len(style.size)?;
+        let [width, height] = style.size;
+        // Only styles that are one closed outline per lane in one color can
+        // be drawn; anything else is refused rather than drawn filled.
+        match style.render_style {
+            RenderStyle::Filled | RenderStyle::Mirrored | RenderStyle::HalfWave => {}
+            render_style => {
+                return Err(Error::InvalidSettings(format!(
+                    "{render_style:?} waveforms can't be drawn as svg"
+                )));
+            }
+        }
+        if style.color_mode != ColorMode::Solid {
+            return Err(Error::InvalidSettings(format!(
+                "{:?} colors can't be drawn as svg",
+                style.color_mode
+            )));
+        }
+
+        let gain = style.normalization.gain(sound, channels, sample_rate) * style.amplitude_scale;
+
+        let mut path = String::new();
+        let mut baselines = String::new();
+        for lane in lane_envelopes(sound, channels, style) {
+            let Some(geometry) = LaneGeometry::new(lane.area, style.vertical_padding) else {
+                continue;
+            };
+
+            let spans: Vec<(f32, f32)> = lane
+                .envelope
+                .iter()
+                .map(|peak| geometry.span(peak, gain, style))
+                .collect();
+            write_outline(&mut path, &spans);
+
+            if style.show_baseline {
+                let _ = write!(
+                    baselines,
+                    r#"<line x1="0" y1="{y}" x2="{width}" y2="{y}"/>"#,
+                    y = number(geometry.baseline(style)),
+                );
+            }
+        }
+
+        let mut document = String::new();
+        let _ = write!(
+            document,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}""#
+        );
+        if options.view_box {
+            let _ = write!(
+                document,
+                r#" viewBox="0 0 {width} {height}" preserveAspectRatio="none""#
+            );
+        }
+        document.push('>');
+
+        if style.background_color[3] > 0 {
+            let _ = write!(
+                document,
+                r#"<rect width="100%" height="100%"{}/>"#,
+                paint("fill", style.background_color)
+            );
+        }
+        if !baselines.is_empty() {
+            let _ = write!(
+                document,
+                r#"<g{}>{baselines}</g>"#,
+                paint("stroke", style.wave_color)
+            );
+        }
+        let _ = write!(
+            document,
+            r#"<path{} d="{path}"/>"#,
+            paint("fill", style.wave_color)
+        );
+
+        if options.time_axis {
+            let frames = sound.len() / channels.max(1);
+            let duration = Duration::from_secs_f64(frames as f64 / sample_rate.max(1) as f64);
+            write_time_axis(&mut document, duration, style);
+        }
+
+        document.push_str("</svg>");
+
+        Ok(SvgSignal { document })
+    }
+
+    pub fn try_from_sample(
+        sample: &MySample,
+        style: &WaveformStyle,
+        options: SvgOptions,
+    ) -> Result<Self, Error> {
+        Self::try_with_style(
+            &sample.samples,
+            sample.channels as usize,
+            sample.sample_rate,
+            style,
+            options,
+        )
+    }
+
+    pub fn save(&self, file_name: &str) {
+        self.try_save(file_name).unwrap();
+    }
+
+    pub fn try_save(&self, file_name: &str) -> Result<(), Error> {
+        std::fs::write(file_name, &self.document)?;
+
+        Ok(())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.document
+    }
+
+    pub fn into_string(self) -> String {
+        self.document
+    }
+}
+
+// Closed outline: along the tops from left to right, then back along the
+// bottoms. Each column sits at its pixel center.
+fn write_outline(path: &mut String, spans: &[(f32, f32)]) {
+    let Some(((first_top, _), _)) = spans.split_first() else {
+        return;
+    };
+
+    let _ = write!(path, "M0.5 {}L", number(*first_top));
+    for (x, (top, _)) in spans.iter().enumerate().skip(1) {
+        let _ = write!(path, "{} {} ", number(x as f32 + 0.5), number(*top));
+    }
+    for (x, (_, bottom)) in spans.iter().enumerate().rev() {
+        let _ = write!(path, "{} {} ", number(x as f32 + 0.5), number(*bottom));
+    }
+    path.pop();
+    path.push('Z');
+}
+
+fn write_time_axis(document: &mut String, duration: Duration, style: &WaveformStyle) {
+    let [width, height] = style.size;
+    let seconds = duration.as_secs_f64();
+    if seconds <= 0.0 {
+        return;
+    }
+
+    let step = tick_step(seconds, TIME_AXIS_TICKS);
+    let tick_top = height as f32 - TIME_AXIS_FONT_SIZE / 2.0;
+    let label_y = tick_top - 2.0;
+
+    let _ = write!(
+        document,
+        r#"<g{}{} font-size="{}" font-family="sans-serif" text-anchor="middle">"#,
+        paint("stroke", style.wave_color),
+        paint("fill", style.wave_color),
+        number(TIME_AXIS_FONT_SIZE)
+    );
+    let mut tick = 0.0;
+    while tick <= seconds {
+        let x = number((tick / seconds * width as f64) as f32);
+        let _ = write!(
+            document,
+            r#"<line x1="{x}" y1="{}" x2="{x}" y2="{height}"/><text x="{x}" y="{}" stroke="none">{}</text>"#,
+            number(tick_top),
+            number(label_y),
+            format_timestamp(Duration::from_secs_f64(tick))
+        );
+        tick += step;
+    }
+    document.push_str("</g>");
+}
+
+// Smallest 1, 2 or 5 times a power of ten that splits `seconds` in at most
+// `target_ticks` intervals.
+pub(crate) fn tick_step(seconds: f64, target_ticks: usize) -> f64 {
+    let raw = seconds / target_ticks.max(1) as f64;
+    let magnitude = 10f64.powf(raw.log10().floor());
+
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|&step| step >= raw)
+        .unwrap_or(10.0 * magnitude)
+}
+
+// mm:ss.mmm
+pub(crate) fn format_timestamp(time: Duration) -> String {
+    let millis = time.as_millis();
+
+    format!(
+        "{:02}:{:02}.{:03}",
+        millis / 60_000,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+fn paint(attribute: &str, color: [u8; 4]) -> String {
+    let [r, g, b, a] = color;
+    if a == 255 {
+        format!(r#" {attribute}="rgb({r},{g},{b})""#)
+    } else {
+        format!(
+            r#" {attribute}="rgb({r},{g},{b})" {attribute}-opacity="{}""#,
+            number(a as f32 / 255.0)
+        )
+    }
+}
+
+// Shortest decimal form with at most two digits after the point.
+fn number(value: f32) -> String {
+    let formatted = format!("{value:.2}");
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    match trimmed {
+        "" | "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}